@@ -1,19 +1,27 @@
-use regex::Regex;
+use regex::bytes::Regex;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::env;
 use std::fs::read_to_string;
 
 #[derive(Default, Debug, Clone, Copy)]
-struct PartNumber<'a> {
+struct PartNumber {
     row: usize,
     col: usize,
-    s: &'a str,
+    len: usize,
     n: u32,
 }
 
-struct Grid<'a> {
-    rows: Vec<&'a str>,
+impl PartNumber {
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (self.col..self.col + self.len).map(|col| Point { row: self.row, col })
+    }
+}
+
+struct Grid {
+    grid: Vec<Vec<u8>>,
+    rows: usize,
+    cols: usize,
 }
 
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
@@ -22,32 +30,33 @@ struct Point {
     col: usize,
 }
 
+// Iterates over the rectangle start_row..=end_row, start_col..=end_col, clamped to the grid.
 struct GridIterator<'a> {
+    start_col: usize,
     end_row: usize,
-
-    start_col: i32,
     end_col: usize,
 
-    grid_rows: usize,
-    grid_cols: usize,
-
-    row: i32,
-    col: i32,
+    row: usize,
+    col: usize,
 
-    grid: &'a Grid<'a>,
+    grid: &'a Grid,
 }
 
 impl<'a> GridIterator<'a> {
-    fn new(start_row: i32, end_row: usize, start_col: i32, end_col: usize, grid: &'a Grid) -> Self {
+    fn new(
+        start_row: usize,
+        end_row: usize,
+        start_col: usize,
+        end_col: usize,
+        grid: &'a Grid,
+    ) -> Self {
         Self {
-            end_row: end_row,
-            start_col: start_col,
-            end_col: end_col,
-            grid_rows: grid.rows.len(),
-            grid_cols: grid.rows[0].len(),
+            start_col,
+            end_row: end_row.min(grid.rows.saturating_sub(1)),
+            end_col: end_col.min(grid.cols.saturating_sub(1)),
             row: start_row,
-            col: start_col - 1,
-            grid: grid,
+            col: start_col,
+            grid,
         }
     }
 }
@@ -55,118 +64,269 @@ impl<'a> GridIterator<'a> {
 impl<'a> Iterator for GridIterator<'a> {
     type Item = (Point, char);
 
-    // next() is the only required method
     fn next(&mut self) -> Option<Self::Item> {
-        if self.row < 0 {
-            self.row = 0;
+        if self.row > self.end_row || self.row >= self.grid.rows || self.col >= self.grid.cols {
+            return None;
         }
 
-        self.col += 1;
+        let point = Point {
+            row: self.row,
+            col: self.col,
+        };
 
-        if self.col > 0 && (self.col as usize > self.end_col || self.col as usize >= self.grid_cols)
-        {
+        self.col += 1;
+        if self.col > self.end_col {
             self.row += 1;
             self.col = self.start_col;
         }
 
-        if self.col < 0 {
-            self.col = 0;
-        }
-
-        if self.row > 0 && (self.row as usize > self.end_row || self.row as usize >= self.grid_rows)
-        {
-            None
-        } else {
-            let c = self.grid.rows[self.row as usize]
-                .chars()
-                .nth(self.col as usize)
-                .unwrap();
-            let point = Point {
-                row: self.row as usize,
-                col: self.col as usize,
-            };
-            Some((point, c))
-        }
+        Some((point, self.grid.at(&point)))
     }
 }
 
-impl<'a> Grid<'a> {
-    fn new(data: &'a str) -> Self {
-        let rows = data.lines().collect();
-        Self { rows }
+impl Grid {
+    fn new(data: &str) -> Self {
+        let grid: Vec<Vec<u8>> = data.lines().map(|s| s.as_bytes().to_vec()).collect();
+        let rows = grid.len();
+        let cols = grid.first().map_or(0, |row| row.len());
+        assert!(grid.iter().all(|row| row.len() == cols));
+        Self { grid, rows, cols }
     }
 
-    fn around(&self, row: usize, col: usize, part_number_len: usize) -> GridIterator {
+    fn at(&self, point: &Point) -> char {
+        self.grid[point.row][point.col] as char
+    }
+
+    fn around(&self, row: usize, col: usize, part_number_len: usize) -> GridIterator<'_> {
         GridIterator::new(
-            row as i32 - 1,
+            row.saturating_sub(1),
             row + 1,
-            col as i32 - 1,
+            col.saturating_sub(1),
             col + part_number_len,
             self,
         )
     }
 }
 
-fn find_part_numbers(row: usize, s: &str) -> Vec<PartNumber> {
+fn find_part_numbers(row: usize, s: &[u8]) -> Vec<PartNumber> {
     let re = Regex::new(r"(\d+)").unwrap();
 
     re.find_iter(s)
         .map(|m| PartNumber {
-            row: row,
+            row,
             col: m.start(),
-            s: m.as_str().into(),
-            n: m.as_str().parse().unwrap(),
+            len: m.len(),
+            n: std::str::from_utf8(m.as_bytes()).unwrap().parse().unwrap(),
         })
         .collect()
 }
 
-fn adjacent_gears(p: &PartNumber, grid: &Grid) -> Vec<Point> {
-    grid.around(p.row, p.col, p.s.len())
-        .filter(|(_point, c): &(Point, char)| *c == '*')
-        .map(|(point, _c): (Point, char)| point)
+fn is_symbol(c: char) -> bool {
+    c != '.' && !c.is_ascii_digit()
+}
+
+fn adjacent_symbols(p: &PartNumber, grid: &Grid) -> Vec<(Point, char)> {
+    grid.around(p.row, p.col, p.len)
+        .filter(|(_point, c): &(Point, char)| is_symbol(*c))
         .collect()
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
-    let grid = Grid::new(data.as_str());
+struct Schematic {
+    parts: Vec<(PartNumber, Vec<(Point, char)>)>,
+}
+
+impl Schematic {
+    fn new(grid: &Grid) -> Self {
+        let parts = grid
+            .grid
+            .iter()
+            .enumerate()
+            .flat_map(|(row, s): (usize, &Vec<u8>)| find_part_numbers(row, s))
+            .map(|part_number| (part_number, adjacent_symbols(&part_number, grid)))
+            .collect();
+        Self { parts }
+    }
+
+    // Part numbers adjacent to at least one symbol accepted by is_class.
+    fn part_numbers_adjacent_to<F>(&self, is_class: F) -> Vec<PartNumber>
+    where
+        F: Fn(char) -> bool,
+    {
+        self.parts
+            .iter()
+            .filter(|(_part_number, symbols)| symbols.iter().any(|(_point, c)| is_class(*c)))
+            .map(|(part_number, _symbols)| *part_number)
+            .collect()
+    }
 
-    let part_numbers = grid
-        .rows
-        .iter()
-        .enumerate()
-        .map(|(row, s): (usize, &&str)| find_part_numbers(row, s))
-        .flatten();
+    fn part_numbers(&self) -> Vec<PartNumber> {
+        self.part_numbers_adjacent_to(is_symbol)
+    }
+
+    fn part_number_sum(&self) -> u32 {
+        self.part_numbers().iter().map(|p| p.n).sum()
+    }
 
-    let gears_to_adjacent_part_numbers = part_numbers
-        .map(|part_number| (part_number, adjacent_gears(&part_number, &grid)))
-        .fold(
+    fn symbols_to_adjacent_part_numbers<F>(&self, is_class: F) -> HashMap<Point, Vec<PartNumber>>
+    where
+        F: Fn(char) -> bool,
+    {
+        self.parts.iter().fold(
             HashMap::new(),
-            |mut dict: HashMap<Point, Vec<PartNumber>>,
-             (part_number, gears): (PartNumber, Vec<Point>)| {
-                gears.iter().for_each(|gear| match dict.entry(*gear) {
-                    Entry::Vacant(e) => {
-                        e.insert(vec![part_number]);
-                    }
-                    Entry::Occupied(mut e) => {
-                        e.get_mut().push(part_number);
-                    }
-                });
+            |mut dict: HashMap<Point, Vec<PartNumber>>, (part_number, symbols)| {
+                symbols.iter().filter(|(_point, c)| is_class(*c)).for_each(
+                    |(point, _c)| match dict.entry(*point) {
+                        Entry::Vacant(e) => {
+                            e.insert(vec![*part_number]);
+                        }
+                        Entry::Occupied(mut e) => {
+                            e.get_mut().push(*part_number);
+                        }
+                    },
+                );
                 dict
             },
-        );
+        )
+    }
 
-    println!(
-        "{}",
-        gears_to_adjacent_part_numbers
+    // Symbols matching gear that are adjacent to exactly n part numbers.
+    fn gears(&self, gear: char, n: usize) -> Vec<(Point, Vec<PartNumber>)> {
+        self.symbols_to_adjacent_part_numbers(|c| c == gear)
+            .into_iter()
+            .filter(|(_gear, part_numbers)| part_numbers.len() == n)
+            .collect()
+    }
+
+    fn gear_ratio_sum(&self) -> u32 {
+        self.gears('*', 2)
             .iter()
-            .filter(|(_gear, part_numbers): &(&Point, &Vec<PartNumber>)| part_numbers.len() == 2)
-            .map(
-                |(_gear, part_numbers): (&Point, &Vec<PartNumber>)| part_numbers[0].n
-                    * part_numbers[1].n
-            )
-            .sum::<u32>()
-    );
+            .map(|(_gear, part_numbers)| part_numbers.iter().map(|p| p.n).product::<u32>())
+            .sum()
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_COUNTED: &str = "\x1b[1;32m";
+const ANSI_UNCONNECTED: &str = "\x1b[2m";
+const ANSI_GEAR: &str = "\x1b[1;33m";
+const ANSI_BAD_GEAR: &str = "\x1b[1;31m";
+
+// Renders the schematic with ANSI colors: part numbers counted in a gear ratio
+// in green, numbers not adjacent to any symbol dimmed, gears with exactly two
+// part numbers in yellow and any other '*' in red.
+fn render(grid: &Grid, schematic: &Schematic) -> String {
+    let mut colors: HashMap<Point, &str> = HashMap::new();
+
+    for (part_number, symbols) in schematic.parts.iter() {
+        if symbols.is_empty() {
+            for point in part_number.points() {
+                colors.insert(point, ANSI_UNCONNECTED);
+            }
+        }
+    }
+    for (row, s) in grid.grid.iter().enumerate() {
+        for (col, c) in s.iter().enumerate() {
+            if *c == b'*' {
+                colors.insert(Point { row, col }, ANSI_BAD_GEAR);
+            }
+        }
+    }
+
+    for (gear, part_numbers) in schematic.gears('*', 2) {
+        colors.insert(gear, ANSI_GEAR);
+        for point in part_numbers.iter().flat_map(|p| p.points()) {
+            colors.insert(point, ANSI_COUNTED);
+        }
+    }
+
+    (0..grid.rows)
+        .map(|row| {
+            (0..grid.cols)
+                .map(|col| {
+                    let point = Point { row, col };
+                    match colors.get(&point) {
+                        Some(color) => format!("{}{}{}", color, grid.at(&point), ANSI_RESET),
+                        None => grid.at(&point).to_string(),
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = args[1].as_str();
+    let data = read_to_string(filename).unwrap();
+    let grid = Grid::new(data.as_str());
+
+    let schematic = Schematic::new(&grid);
+
+    println!("part number sum: {}", schematic.part_number_sum());
+    println!("gear ratio sum: {}", schematic.gear_ratio_sum());
+
+    if args.get(2).map(|s| s.as_str()) == Some("--render") {
+        println!("{}", render(&grid, &schematic));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn around(grid: &Grid, row: usize, col: usize, len: usize) -> Vec<(usize, usize, char)> {
+        grid.around(row, col, len)
+            .map(|(point, c)| (point.row, point.col, c))
+            .collect()
+    }
+
+    #[test]
+    fn border_sums() {
+        let grid = Grid::new(include_str!("test_border.txt"));
+        let schematic = Schematic::new(&grid);
+        assert_eq!(schematic.part_number_sum(), 115);
+        assert_eq!(schematic.gear_ratio_sum(), 60);
+    }
+
+    #[test]
+    fn border_neighbors() {
+        let grid = Grid::new(include_str!("test_border.txt"));
+
+        // Top left corner.
+        assert_eq!(
+            around(&grid, 0, 0, 2),
+            vec![(0, 0, '1'), (0, 1, '2'), (0, 2, '.'), (1, 0, '*'), (1, 1, '.'), (1, 2, '.')]
+        );
+        // Top right corner.
+        assert_eq!(
+            around(&grid, 0, 8, 2),
+            vec![(0, 7, '.'), (0, 8, '3'), (0, 9, '4'), (1, 7, '.'), (1, 8, '#'), (1, 9, '.')]
+        );
+        // Left edge.
+        assert_eq!(
+            around(&grid, 2, 0, 1),
+            vec![(1, 0, '*'), (1, 1, '.'), (2, 0, '5'), (2, 1, '.'), (3, 0, '.'), (3, 1, '.')]
+        );
+        // Right edge.
+        assert_eq!(
+            around(&grid, 2, 9, 1),
+            vec![(1, 8, '#'), (1, 9, '.'), (2, 8, '.'), (2, 9, '6'), (3, 8, '.'), (3, 9, '+')]
+        );
+        // Bottom left corner.
+        assert_eq!(
+            around(&grid, 8, 0, 2),
+            vec![(7, 0, '$'), (7, 1, '.'), (7, 2, '.'), (8, 0, '3'), (8, 1, '1'), (8, 2, '.')]
+        );
+        // Bottom edge.
+        assert_eq!(
+            around(&grid, 8, 3, 1),
+            vec![(7, 2, '.'), (7, 3, '.'), (7, 4, '.'), (8, 2, '.'), (8, 3, '4'), (8, 4, '*')]
+        );
+        // Bottom right corner.
+        assert_eq!(
+            around(&grid, 8, 9, 1),
+            vec![(7, 8, '.'), (7, 9, '@'), (8, 8, '.'), (8, 9, '5')]
+        );
+    }
 }
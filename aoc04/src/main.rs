@@ -1,7 +1,31 @@
-use regex::Regex;
 use std::collections::HashSet;
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::process;
+
+#[derive(Debug)]
+enum CardError {
+    Malformed(String),
+    BadNumber { id: usize, word: String },
+    DuplicateNumber { id: usize, n: usize },
+    NonSequentialId { expected: usize, found: usize },
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardError::Malformed(line) => write!(f, "malformed card: {:?}", line),
+            CardError::BadNumber { id, word } => write!(f, "card {}: bad number {:?}", id, word),
+            CardError::DuplicateNumber { id, n } => {
+                write!(f, "card {}: number {} appears twice in one list", id, n)
+            }
+            CardError::NonSequentialId { expected, found } => {
+                write!(f, "expected card {}, found card {}", expected, found)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 struct Card {
@@ -11,27 +35,32 @@ struct Card {
 }
 
 impl Card {
-    fn new(s: &str) -> Self {
-        let re =
-            Regex::new(r"Card\s+(?P<id>\d+): (?P<winning>[\d\s]+) \| (?P<have>[\d\s]+)").unwrap();
-        let caps = re.captures(s).unwrap();
-        let id: usize = caps.name("id").unwrap().as_str().parse().unwrap();
-        let winning: HashSet<usize> = caps
-            .name("winning")
-            .unwrap()
-            .as_str()
-            .split_whitespace()
-            .map(|s| s.parse::<usize>().unwrap())
-            .collect();
-        let have: HashSet<usize> = caps
-            .name("have")
-            .unwrap()
-            .as_str()
-            .split_whitespace()
-            .map(|s| s.parse::<usize>().unwrap())
-            .collect();
+    fn new(s: &str) -> Result<Self, CardError> {
+        let malformed = || CardError::Malformed(s.to_string());
+        let (id, numbers) = s
+            .strip_prefix("Card")
+            .and_then(|s| s.split_once(':'))
+            .ok_or_else(malformed)?;
+        let id: usize = id.trim().parse().map_err(|_| malformed())?;
+        let (winning, have) = numbers.split_once('|').ok_or_else(malformed)?;
+        let winning = Self::parse_numbers(id, winning)?;
+        let have = Self::parse_numbers(id, have)?;
+
+        Ok(Self { id, winning, have })
+    }
 
-        Self { id, winning, have }
+    fn parse_numbers(id: usize, s: &str) -> Result<HashSet<usize>, CardError> {
+        let mut numbers: HashSet<usize> = HashSet::new();
+        for word in s.split_whitespace() {
+            let n = word.parse::<usize>().map_err(|_| CardError::BadNumber {
+                id,
+                word: word.to_string(),
+            })?;
+            if !numbers.insert(n) {
+                return Err(CardError::DuplicateNumber { id, n });
+            }
+        }
+        Ok(numbers)
     }
 
     fn count(&self) -> usize {
@@ -46,26 +75,193 @@ impl Card {
     }
 }
 
-fn count_recursive_cards(cards: &[Card], all_cards: &Vec<Card>) -> usize {
-    let mut count = cards.len(); // count these cards
-    for c in cards.iter() {
-        let v = c.count();
-        if v > 0 {
-            let extra_cards = &all_cards[c.id..c.id + v];
-            count += count_recursive_cards(extra_cards, all_cards);
+// Parses one card per line, requiring ids to run 1, 2, 3, ... so that card
+// N is at index N - 1.
+fn parse_cards(data: &str) -> Result<Vec<Card>, CardError> {
+    let mut cards: Vec<Card> = Vec::new();
+    for line in data.lines().filter(|l| !l.trim().is_empty()) {
+        let card = Card::new(line)?;
+        if card.id != cards.len() + 1 {
+            return Err(CardError::NonSequentialId {
+                expected: cards.len() + 1,
+                found: card.id,
+            });
+        }
+        cards.push(card);
+    }
+    Ok(cards)
+}
+
+// Cards whose winnings run past the last card, as (id, cards won, cards
+// that follow it). The extra winnings are dropped.
+fn clamped_winnings(cards: &[Card]) -> Vec<(usize, usize, usize)> {
+    cards
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| i + c.count() >= cards.len())
+        .map(|(i, c)| (c.id, c.count(), cards.len() - i - 1))
+        .collect()
+}
+
+fn warn_clamped_winnings(cards: &[Card]) {
+    for (id, won, follow) in clamped_winnings(cards) {
+        eprintln!(
+            "warning: card {} wins {} cards but only {} follow it",
+            id, won, follow
+        );
+    }
+}
+
+// Number of copies of each card held once all winnings are processed, indexed
+// like cards. Each card's copies win one more of each of the following count()
+// cards, so a single forward pass is enough.
+fn card_copies(cards: &[Card]) -> Vec<usize> {
+    let mut copies = vec![1; cards.len()];
+    for (i, c) in cards.iter().enumerate() {
+        for j in (i + 1)..(i + 1 + c.count()).min(cards.len()) {
+            copies[j] += copies[i];
         }
     }
-    count
+    copies
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
+    let data = read_to_string(filename).unwrap();
 
-    let cards: Vec<Card> = data.lines().map(Card::new).collect();
+    let cards = parse_cards(&data).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+    warn_clamped_winnings(&cards);
 
     println!("{}", cards.iter().map(|c| c.value()).sum::<usize>());
 
-    println!("{}", count_recursive_cards(&cards, &cards));
+    let copies = card_copies(&cards);
+    println!("{}", copies.iter().sum::<usize>());
+
+    if args.get(2).map(|s| s.as_str()) == Some("--copies") {
+        for (c, n) in cards.iter().zip(copies.iter()) {
+            println!("Card {}: {}", c.id, n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_recursive_cards(cards: &[Card], all_cards: &[Card]) -> usize {
+        let mut count = cards.len(); // count these cards
+        for c in cards.iter() {
+            let v = c.count();
+            if v > 0 {
+                let extra_cards = &all_cards[c.id..(c.id + v).min(all_cards.len())];
+                count += count_recursive_cards(extra_cards, all_cards);
+            }
+        }
+        count
+    }
+
+    // A table where card i + 1 has wins[i] matching numbers.
+    fn cards_with_wins(wins: &[usize]) -> Vec<Card> {
+        let data: String = wins
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let winning: Vec<String> = (1..=5).map(|x| x.to_string()).collect();
+                let have: Vec<String> = (1..=5).map(|x| (x + 5 - n).to_string()).collect();
+                format!(
+                    "Card {}: {} | {}\n",
+                    i + 1,
+                    winning.join(" "),
+                    have.join(" ")
+                )
+            })
+            .collect();
+        parse_cards(&data).unwrap()
+    }
+
+    fn assert_agrees(cards: &[Card]) {
+        assert_eq!(
+            card_copies(cards).iter().sum::<usize>(),
+            count_recursive_cards(cards, cards)
+        );
+    }
+
+    #[test]
+    fn copies_match_recursive_example() {
+        let cards = parse_cards(include_str!("test.txt")).unwrap();
+        assert_eq!(card_copies(&cards), vec![1, 2, 4, 8, 14, 1]);
+        assert_agrees(&cards);
+    }
+
+    #[test]
+    fn copies_match_recursive_synthetic() {
+        for wins in [
+            vec![],
+            vec![0],
+            vec![5],
+            vec![1, 1, 1, 1, 0],
+            vec![3, 0, 2, 1, 0],
+            vec![2, 5, 4, 3, 2, 1],
+            vec![0, 4, 0, 2, 1, 0, 3, 1],
+        ] {
+            let cards = cards_with_wins(&wins);
+            for (card, n) in cards.iter().zip(wins.iter()) {
+                assert_eq!(card.count(), *n);
+            }
+            assert_agrees(&cards);
+        }
+    }
+
+    #[test]
+    fn malformed_card() {
+        assert!(matches!(
+            parse_cards("Card 1 41 48 | 83 86"),
+            Err(CardError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse_cards("Card 1: 41 48 83 86"),
+            Err(CardError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn bad_number() {
+        match parse_cards("Card 1: 41 4x | 83 86") {
+            Err(CardError::BadNumber { id, word }) => assert_eq!((id, word.as_str()), (1, "4x")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_number() {
+        match parse_cards("Card 1: 41 48 | 83 86\nCard 2: 1 2 | 7 9 7") {
+            Err(CardError::DuplicateNumber { id, n }) => assert_eq!((id, n), (2, 7)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_sequential_id() {
+        match parse_cards("Card 1: 41 48 | 83 86\nCard 3: 1 2 | 7 9") {
+            Err(CardError::NonSequentialId { expected, found }) => {
+                assert_eq!((expected, found), (2, 3))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn clamped() {
+        let cards = cards_with_wins(&[1, 3, 1, 0]);
+        assert_eq!(clamped_winnings(&cards), vec![(2, 3, 2)]);
+        let cards = cards_with_wins(&[2, 0, 1]);
+        assert_eq!(clamped_winnings(&cards), vec![(3, 1, 0)]);
+        assert_eq!(card_copies(&cards), vec![1, 2, 2]);
+        let cards = parse_cards(include_str!("test.txt")).unwrap();
+        assert!(clamped_winnings(&cards).is_empty());
+    }
 }
@@ -1,10 +1,12 @@
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::iter;
 use std::iter::Peekable;
+use std::ops::Range;
+use std::process;
 
-use rayon::prelude::*;
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Mapping {
     dest: u64,
     src: u64,
@@ -22,68 +24,302 @@ impl Mapping {
 
     fn map(&self, n: u64) -> Option<u64> {
         if n >= self.src && n < self.src + self.size {
-            return Some(self.dest + (n - self.src));
+            Some(self.dest + (n - self.src))
         } else {
+            None
+        }
+    }
+
+    // Splits range into the part covered by this mapping, translated to the
+    // destination, and the uncovered parts before and after it.
+    fn map_range(&self, range: &Range<u64>) -> (Option<Range<u64>>, Vec<Range<u64>>) {
+        let src_end = self.src + self.size;
+        let start = range.start.max(self.src);
+        let end = range.end.min(src_end);
+        if start >= end {
+            return (None, vec![range.clone()]);
+        }
+
+        let mapped = (self.dest + (start - self.src))..(self.dest + (end - self.src));
+        let unmapped = [range.start..start, end..range.end]
+            .into_iter()
+            .filter(|r| !r.is_empty())
+            .collect();
+        (Some(mapped), unmapped)
+    }
+
+    // The sources that this mapping sends into range.
+    fn unmap_range(&self, range: &Range<u64>) -> Option<Range<u64>> {
+        let start = range.start.max(self.dest);
+        let end = range.end.min(self.dest + self.size);
+        if start >= end {
             return None;
         }
+        Some((self.src + (start - self.dest))..(self.src + (end - self.dest)))
+    }
+}
+
+#[derive(Debug)]
+enum ChainError {
+    BadHeader(String),
+    MissingMap { from: String },
+    Cycle { at: String },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::BadHeader(header) => write!(f, "bad map header {:?}", header),
+            ChainError::MissingMap { from } => write!(f, "no map from {}", from),
+            ChainError::Cycle { at } => write!(f, "maps loop back to {}", at),
+        }
     }
 }
 
 #[derive(Debug)]
 struct Map<'a> {
-    header: &'a str,
+    source: &'a str,
+    destination: &'a str,
     mappings: Vec<Mapping>,
 }
 
 impl<'a> Map<'a> {
-    fn new(header: &'a str, lines: Vec<&str>) -> Self {
-        Self {
-            header: header,
-            mappings: lines.iter().map(|s| Mapping::new(*s)).collect(),
-        }
+    fn new(header: &'a str, lines: Vec<&str>) -> Result<Self, ChainError> {
+        let (source, destination) = header
+            .strip_suffix(" map:")
+            .and_then(|h| h.split_once("-to-"))
+            .ok_or_else(|| ChainError::BadHeader(header.to_string()))?;
+        Ok(Self {
+            source,
+            destination,
+            mappings: lines.iter().map(|s| Mapping::new(s)).collect(),
+        })
     }
 
     fn map(&self, n: u64) -> u64 {
         for mapping in &self.mappings {
-            let v = mapping.map(n);
-            if v.is_some() {
-                return v.unwrap();
+            if let Some(v) = mapping.map(n) {
+                return v;
+            }
+        }
+        n
+    }
+
+    fn map_ranges(&self, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+        let mut mapped: Vec<Range<u64>> = Vec::new();
+        let mut unmapped: Vec<Range<u64>> = ranges.to_vec();
+        for mapping in &self.mappings {
+            let mut remaining: Vec<Range<u64>> = Vec::new();
+            for range in unmapped.iter() {
+                let (m, mut u) = mapping.map_range(range);
+                mapped.extend(m);
+                remaining.append(&mut u);
+            }
+            unmapped = remaining;
+        }
+        // Anything not covered by a mapping keeps its number.
+        mapped.append(&mut unmapped);
+        mapped
+    }
+
+    // Splits the whole number line into mappings that don't overlap, sorted by
+    // source, with numbers not covered by any mapping mapped to themselves.
+    fn pieces(&self) -> Vec<Mapping> {
+        let mut bounds: Vec<u64> = self
+            .mappings
+            .iter()
+            .flat_map(|m| [m.src, m.src + m.size])
+            .chain([0, u64::MAX])
+            .collect();
+        bounds.sort();
+        bounds.dedup();
+        bounds
+            .windows(2)
+            .map(|w| Mapping {
+                dest: self.map(w[0]),
+                src: w[0],
+                size: w[1] - w[0],
+            })
+            .collect()
+    }
+
+    // All numbers that map into ranges.
+    fn unmap_ranges(&self, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+        merge_ranges(
+            self.pieces()
+                .iter()
+                .flat_map(|piece| ranges.iter().filter_map(|r| piece.unmap_range(r)))
+                .collect(),
+        )
+    }
+
+    // Pairs of mappings whose source ranges overlap, where only the first one
+    // listed is ever used.
+    fn overlaps(&self) -> Vec<(&Mapping, &Mapping)> {
+        let mut overlaps: Vec<(&Mapping, &Mapping)> = Vec::new();
+        for (i, a) in self.mappings.iter().enumerate() {
+            for b in self.mappings[i + 1..].iter() {
+                if a.src < b.src + b.size && b.src < a.src + a.size {
+                    overlaps.push((a, b));
+                }
             }
         }
-        return n;
+        overlaps
+    }
+}
+
+// Prints the map in the almanac format so it can be read back by parse_maps.
+impl<'a> fmt::Display for Map<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}-to-{} map:", self.source, self.destination)?;
+        for mapping in self.mappings.iter() {
+            writeln!(f, "{} {} {}", mapping.dest, mapping.src, mapping.size)?;
+        }
+        Ok(())
+    }
+}
+
+fn merge_ranges(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+// The maps that lead from category from to category to, in order.
+fn chain<'a, 'b>(
+    maps: &'b [Map<'a>],
+    from: &str,
+    to: &str,
+) -> Result<Vec<&'b Map<'a>>, ChainError> {
+    let mut chain: Vec<&Map> = Vec::new();
+    let mut category = from;
+    while category != to {
+        if chain.len() == maps.len() {
+            return Err(ChainError::Cycle {
+                at: category.to_string(),
+            });
+        }
+        let map = maps
+            .iter()
+            .find(|map| map.source == category)
+            .ok_or_else(|| ChainError::MissingMap {
+                from: category.to_string(),
+            })?;
+        chain.push(map);
+        category = map.destination;
     }
+    Ok(chain)
 }
 
-fn do_map(maps: &Vec<Map>, mut n: u64) -> u64 {
+fn do_map(maps: &[&Map], mut n: u64) -> u64 {
     for map in maps.iter() {
         n = map.map(n);
     }
     n
 }
 
-fn parse_maps<'a>(lines: &'a mut Peekable<std::str::Lines<'a>>) -> Vec<Map<'a>> {
+// The number at every step along maps, starting with n.
+fn map_path(maps: &[&Map], n: u64) -> Vec<u64> {
+    let mut path = vec![n];
+    for map in maps.iter() {
+        path.push(map.map(*path.last().unwrap()));
+    }
+    path
+}
+
+fn do_map_ranges(maps: &[&Map], ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+    maps.iter()
+        .fold(ranges.to_vec(), |ranges, map| map.map_ranges(&ranges))
+}
+
+// All numbers that map into ranges through every map in maps.
+fn do_unmap_ranges(maps: &[&Map], ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+    maps.iter()
+        .rev()
+        .fold(ranges.to_vec(), |ranges, map| map.unmap_ranges(&ranges))
+}
+
+// Combines a chain of maps from source to destination into a single map, with
+// mappings that don't overlap. An empty chain gives a map with no mappings,
+// which sends every number to itself.
+fn compose<'a>(maps: &[&Map<'a>], source: &'a str, destination: &'a str) -> Map<'a> {
+    let mut pieces = vec![Mapping {
+        dest: 0,
+        src: 0,
+        size: u64::MAX,
+    }];
+    for map in maps.iter() {
+        let next = map.pieces();
+        let mut composed: Vec<Mapping> = Vec::new();
+        for p in pieces.iter() {
+            for q in next.iter() {
+                let start = p.dest.max(q.src);
+                let end = (p.dest + p.size).min(q.src + q.size);
+                if start >= end {
+                    continue;
+                }
+                let piece = Mapping {
+                    dest: q.dest + (start - q.src),
+                    src: p.src + (start - p.dest),
+                    size: end - start,
+                };
+                match composed.last_mut() {
+                    Some(last)
+                        if last.src + last.size == piece.src
+                            && last.dest + last.size == piece.dest =>
+                    {
+                        last.size += piece.size
+                    }
+                    _ => composed.push(piece),
+                }
+            }
+        }
+        pieces = composed;
+    }
+
+    Map {
+        source,
+        destination,
+        mappings: pieces.into_iter().filter(|p| p.src != p.dest).collect(),
+    }
+}
+
+fn parse_maps<'a>(
+    lines: &'a mut Peekable<std::str::Lines<'a>>,
+) -> Result<Vec<Map<'a>>, ChainError> {
     let mut maps: Vec<Map> = Vec::new();
-    while lines.peek() != None {
+    while lines.peek().is_some() {
         let map_header = lines.next().unwrap();
         let map_lines: Vec<&str> = lines.take_while(|l| !l.is_empty()).collect();
-        maps.push(Map::new(map_header, map_lines));
+        maps.push(Map::new(map_header, map_lines)?);
     }
-    maps
-}
-
-fn min_of_range(maps: &Vec<Map>, start: u64, size: u64) -> u64 {
-    let range = start..start + size;
-    range
-        .into_par_iter()
-        .map(|s| do_map(&maps, s))
-        .min()
-        .unwrap()
+    Ok(maps)
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
+    let paths = args[2..].iter().any(|a| a == "--paths");
+    let composed = args[2..].iter().any(|a| a == "--compose");
+    let inverse: Option<Range<u64>> = args
+        .iter()
+        .position(|a| a == "--inverse")
+        .map(|i| args[i + 1].parse().unwrap()..args[i + 2].parse().unwrap());
+    let from = args
+        .iter()
+        .position(|a| a == "--from")
+        .map_or("seed", |i| args[i + 1].as_str());
+    let to = args
+        .iter()
+        .position(|a| a == "--to")
+        .map_or("location", |i| args[i + 1].as_str());
+    let data = read_to_string(filename).unwrap();
 
     let mut lines = data.lines().peekable();
 
@@ -96,19 +332,215 @@ fn main() {
         .map(|s| s.parse::<u64>().unwrap())
         .collect::<Vec<u64>>();
 
-    dbg!(&starting_seeds);
-
     lines.next();
 
-    let maps = parse_maps(&mut lines);
+    let maps = parse_maps(&mut lines).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+    for map in maps.iter() {
+        for (a, b) in map.overlaps() {
+            eprintln!(
+                "warning: {}-to-{} map: source ranges {:?} and {:?} overlap",
+                map.source,
+                map.destination,
+                a.src..a.src + a.size,
+                b.src..b.src + b.size
+            );
+        }
+    }
+
+    let maps = chain(&maps, from, to).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+
+    if paths {
+        let categories: Vec<&str> = iter::once(from)
+            .chain(maps.iter().map(|map| map.destination))
+            .collect();
+        for seed in starting_seeds.iter() {
+            let path = map_path(&maps, *seed);
+            println!(
+                "{}",
+                categories
+                    .iter()
+                    .zip(path.iter())
+                    .map(|(category, n)| format!("{} {}", category, n))
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            );
+        }
+    }
+
+    // Part 1: every number is a seed, or whatever category --from names.
     println!(
         "{}",
         starting_seeds
-            .chunks(2)
-            .collect::<Vec<&[u64]>>()
-            .into_par_iter()
-            .map(|r| min_of_range(&maps, r[0], r[1]))
+            .iter()
+            .map(|seed| do_map(&maps, *seed))
             .min()
             .unwrap()
     );
+
+    // Part 2: numbers are pairs of range start and length.
+    let seed_ranges: Vec<Range<u64>> = starting_seeds
+        .chunks(2)
+        .map(|r| r[0]..r[0] + r[1])
+        .collect();
+    let lowest = do_map_ranges(&maps, &seed_ranges)
+        .iter()
+        .map(|r| r.start)
+        .min()
+        .unwrap();
+    println!("{}", lowest);
+
+    if composed {
+        print!("{}", compose(&maps, from, to));
+    }
+
+    if let Some(range) = inverse {
+        for seeds in do_unmap_ranges(&maps, &[range]) {
+            println!("{:?}", seeds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+    use std::slice;
+
+    // Brute force check for do_map_ranges, maps every seed individually.
+    fn min_of_range(maps: &[&Map], start: u64, size: u64) -> u64 {
+        let range = start..start + size;
+        range
+            .into_par_iter()
+            .map(|s| do_map(maps, s))
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn map_ranges_matches_brute_force() {
+        let data = include_str!("test.txt");
+        let mut lines = data.lines().peekable();
+        let seeds: Vec<u64> = lines
+            .next()
+            .unwrap()
+            .strip_prefix("seeds: ")
+            .unwrap()
+            .split_ascii_whitespace()
+            .map(|s| s.parse::<u64>().unwrap())
+            .collect();
+        lines.next();
+        let maps = parse_maps(&mut lines).unwrap();
+        let maps = chain(&maps, "seed", "location").unwrap();
+
+        let seed_ranges: Vec<Range<u64>> = seeds.chunks(2).map(|r| r[0]..r[0] + r[1]).collect();
+        let lowest = do_map_ranges(&maps, &seed_ranges)
+            .iter()
+            .map(|r| r.start)
+            .min()
+            .unwrap();
+        let brute_force = seed_ranges
+            .par_iter()
+            .map(|r| min_of_range(&maps, r.start, r.end - r.start))
+            .min()
+            .unwrap();
+        assert_eq!(lowest, 46);
+        assert_eq!(lowest, brute_force);
+    }
+
+    #[test]
+    fn compose_matches_chain() {
+        let (_seeds, almanac) = include_str!("test.txt").split_once("\n\n").unwrap();
+        let mut lines = almanac.lines().peekable();
+        let maps = parse_maps(&mut lines).unwrap();
+        for to in ["seed", "soil", "humidity", "location"] {
+            let chain = chain(&maps, "seed", to).unwrap();
+            let composed = compose(&chain, "seed", to);
+            assert_eq!((composed.source, composed.destination), ("seed", to));
+            for seed in 0..120 {
+                assert_eq!(composed.map(seed), do_map(&chain, seed));
+            }
+        }
+    }
+
+    // Whether n is in any of ranges.
+    fn covers(ranges: &[Range<u64>], n: u64) -> bool {
+        ranges.iter().any(|r| r.contains(&n))
+    }
+
+    #[test]
+    fn unmap_ranges_matches_brute_force() {
+        let (_seeds, almanac) = include_str!("test.txt").split_once("\n\n").unwrap();
+        let mut lines = almanac.lines().peekable();
+        let maps = parse_maps(&mut lines).unwrap();
+        let targets = [0..1, 46..47, 10..30, 50..100, 60..61];
+        for target in targets.iter() {
+            let target = slice::from_ref(target);
+            for map in maps.iter() {
+                let preimage = map.unmap_ranges(target);
+                for n in 0..120 {
+                    assert_eq!(covers(&preimage, n), covers(target, map.map(n)), "{}", n);
+                }
+            }
+            let chain = chain(&maps, "seed", "location").unwrap();
+            let preimage = do_unmap_ranges(&chain, target);
+            for n in 0..120 {
+                assert_eq!(
+                    covers(&preimage, n),
+                    covers(target, do_map(&chain, n)),
+                    "{}",
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lowest_location_comes_from_a_seed_range() {
+        let (seeds, almanac) = include_str!("test.txt").split_once("\n\n").unwrap();
+        let seeds: Vec<u64> = seeds
+            .strip_prefix("seeds: ")
+            .unwrap()
+            .split_ascii_whitespace()
+            .map(|s| s.parse::<u64>().unwrap())
+            .collect();
+        let seed_ranges: Vec<Range<u64>> = seeds.chunks(2).map(|r| r[0]..r[0] + r[1]).collect();
+        let mut lines = almanac.lines().peekable();
+        let maps = parse_maps(&mut lines).unwrap();
+        let chain = chain(&maps, "seed", "location").unwrap();
+
+        let lowest = 46..47;
+        let preimage = do_unmap_ranges(&chain, slice::from_ref(&lowest));
+        assert!(preimage.iter().any(|r| seed_ranges
+            .iter()
+            .any(|s| r.start < s.end && s.start < r.end)));
+    }
+
+    #[test]
+    fn composed_map_round_trips() {
+        let (_seeds, almanac) = include_str!("test.txt").split_once("\n\n").unwrap();
+        let mut lines = almanac.lines().peekable();
+        let maps = parse_maps(&mut lines).unwrap();
+        let chain = chain(&maps, "seed", "location").unwrap();
+        let composed = compose(&chain, "seed", "location");
+
+        let printed = composed.to_string();
+        let mut lines = printed.lines().peekable();
+        let reparsed = parse_maps(&mut lines).unwrap();
+        assert_eq!(reparsed.len(), 1);
+        let reparsed = &reparsed[0];
+        assert_eq!(
+            (reparsed.source, reparsed.destination),
+            ("seed", "location")
+        );
+        assert_eq!(reparsed.to_string(), printed);
+        for seed in 0..120 {
+            assert_eq!(reparsed.map(seed), do_map(&chain, seed));
+        }
+    }
 }
@@ -11,46 +11,109 @@ impl Race {
         Self { time, distance }
     }
 
+    fn travelled(&self, hold: u128) -> u128 {
+        hold * (self.time as u128 - hold)
+    }
+
+    // Holding for t travels t * (time - t), so the winning hold times are the
+    // integers strictly between the roots of t^2 - time * t + distance, which
+    // are symmetric around time / 2.
     fn count_wins(&self) -> u64 {
-        (1..(self.time - 1))
-            .map(|t| t * (self.time - t))
-            .filter(|t| *t > self.distance)
+        let time = self.time as u128;
+        let distance = self.distance as u128;
+        let discriminant = match (time * time).checked_sub(4 * distance) {
+            Some(d) if d > 0 => d,
+            _ => return 0,
+        };
+
+        // Rounding in isqrt and the division can put the estimate off by one
+        // either way, so step to the first winning hold time exactly.
+        let mut first = (time - discriminant.isqrt()) / 2;
+        while first > 0 && self.travelled(first - 1) > distance {
+            first -= 1;
+        }
+        while first <= time / 2 && self.travelled(first) <= distance {
+            first += 1;
+        }
+        if first > time / 2 {
+            return 0;
+        }
+
+        (time - 2 * first + 1).try_into().unwrap()
+    }
+
+    #[cfg(test)]
+    fn count_wins_brute_force(&self) -> u64 {
+        (0..=self.time)
+            .filter(|t| self.travelled(*t as u128) > self.distance as u128)
             .count()
             .try_into()
             .unwrap()
     }
 }
 
+// Reads the numbers after prefix both as separate values and, ignoring the
+// spaces between them, as one kerned value.
+fn parse_line(line: &str, prefix: &str) -> (Vec<u64>, u64) {
+    let numbers = line.strip_prefix(prefix).unwrap();
+    let separate = numbers
+        .split_whitespace()
+        .map(|s| s.parse::<u64>().unwrap())
+        .collect();
+    let kerned = numbers.replace(' ', "").parse::<u64>().unwrap();
+    (separate, kerned)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
+    let data = read_to_string(filename).unwrap();
     let mut lines = data.lines();
 
-    let times: Vec<u64> = lines
-        .next()
-        .unwrap()
-        .strip_prefix("Time:")
-        .unwrap()
-        .replace(" ", "")
-        .split_whitespace()
-        .map(|s| s.parse::<u64>().unwrap())
-        .collect();
-    let distances: Vec<u64> = lines
-        .next()
-        .unwrap()
-        .strip_prefix("Distance:")
-        .unwrap()
-        .replace(" ", "")
-        .split_whitespace()
-        .map(|s| s.parse::<u64>().unwrap())
-        .collect();
+    let (times, time) = parse_line(lines.next().unwrap(), "Time:");
+    let (distances, distance) = parse_line(lines.next().unwrap(), "Distance:");
 
     let races: Vec<Race> = times
         .iter()
         .zip(distances.iter())
         .map(|(time, distance)| Race::new(*time, *distance))
         .collect();
+    let kerned_race = Race::new(time, distance);
 
     println!("{}", races.iter().map(|r| r.count_wins()).product::<u64>());
+    println!("{}", kerned_race.count_wins());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_wins_matches_brute_force() {
+        for time in 0..200 {
+            for distance in 0..=(time * time / 4 + 1) {
+                let race = Race::new(time, distance);
+                assert_eq!(
+                    race.count_wins(),
+                    race.count_wins_brute_force(),
+                    "{:?}",
+                    race
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn example() {
+        let mut lines = include_str!("test.txt").lines();
+        let (times, time) = parse_line(lines.next().unwrap(), "Time:");
+        let (distances, distance) = parse_line(lines.next().unwrap(), "Distance:");
+        let product: u64 = times
+            .iter()
+            .zip(distances.iter())
+            .map(|(time, distance)| Race::new(*time, *distance).count_wins())
+            .product();
+        assert_eq!(product, 288);
+        assert_eq!(Race::new(time, distance).count_wins(), 71503);
+    }
 }
@@ -1,232 +1,236 @@
-use regex::Regex;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::env;
 use std::fs::read_to_string;
 
 #[derive(Debug, Clone)]
-struct Hand {
-    cards: [u8; 5],
-    card_counts: Vec<CardCount>,
-    bid: u64,
+struct Ruleset {
+    // Cards from weakest to strongest.
+    order: String,
+    // Card that takes whatever value makes the best hand type.
+    joker: Option<char>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct CardCount {
-    count: usize,
-    card: u8,
-}
-
-impl Ord for CardCount {
-    fn cmp(&self, other: &Self) -> Ordering {
-        (usize::MAX - self.count, self.card).cmp(&(usize::MAX - other.count, other.card))
+impl Ruleset {
+    fn new(order: &str, joker: Option<char>) -> Self {
+        assert!(joker.is_none_or(|j| order.contains(j)));
+        Self {
+            order: order.to_string(),
+            joker,
+        }
     }
-}
 
-impl PartialOrd for CardCount {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    // J is a jack, between T and Q.
+    fn standard() -> Self {
+        Self::new("23456789TJQKA", None)
     }
-}
 
-impl Hand {
-    fn new(hand_str: &str) -> Self {
-        let re = Regex::new(r"(?P<cards_string>[2-9TJQKA]+) (?P<bid>[\d]+)").unwrap();
-        let caps = re.captures(hand_str).unwrap();
+    // J is a joker, the weakest card but wild when picking the hand type.
+    fn jokers() -> Self {
+        Self::new("J23456789TQKA", Some('J'))
+    }
 
-        let mut cards: [u8; 5] = Default::default();
-        let mut cards_count_map: HashMap<u8, usize> = Default::default();
+    fn strength(&self, card: char) -> u8 {
+        self.order
+            .find(card)
+            .unwrap_or_else(|| panic!("unknown card {:?}", card)) as u8
+    }
+}
 
-        let cards_string = caps.name("cards_string").unwrap().as_str();
-        let bid = caps.name("bid").unwrap().as_str().parse::<u64>().unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum HandType {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+}
 
-        for (i, c) in cards_string.chars().enumerate() {
-            cards[i] = Self::card_char_to_int(c);
-            *cards_count_map.entry(cards[i]).or_insert(0) += 1;
+impl HandType {
+    // Jokers always do best by joining the largest group of the other cards.
+    fn new(cards: &str, joker: Option<char>) -> Self {
+        let mut card_counts: HashMap<char, usize> = HashMap::new();
+        let mut jokers = 0;
+        for c in cards.chars() {
+            if Some(c) == joker {
+                jokers += 1;
+            } else {
+                *card_counts.entry(c).or_insert(0) += 1;
+            }
         }
 
-        let mut card_counts: Vec<CardCount> = cards_count_map
-            .iter()
-            .map(|(card, count)| CardCount {
-                card: *card,
-                count: *count,
-            })
-            .collect();
-        card_counts.sort();
+        let mut counts: Vec<usize> = card_counts.into_values().collect();
+        counts.sort_by(|a, b| b.cmp(a));
+        if counts.is_empty() {
+            counts.push(0);
+        }
+        counts[0] += jokers;
+
+        match (counts[0], counts.get(1)) {
+            (5, _) => HandType::FiveOfAKind,
+            (4, _) => HandType::FourOfAKind,
+            (3, Some(2)) => HandType::FullHouse,
+            (3, _) => HandType::ThreeOfAKind,
+            (2, Some(2)) => HandType::TwoPair,
+            (2, _) => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
+    }
 
-        Self {
-            cards,
-            card_counts,
-            bid,
+    // Tries every replacement for every joker, used to check HandType::new.
+    #[cfg(test)]
+    fn brute_force(cards: &str, ruleset: &Ruleset) -> Self {
+        let joker = ruleset.joker;
+        match cards.chars().position(|c| Some(c) == joker) {
+            Some(pos) => ruleset
+                .order
+                .chars()
+                .filter(|c| Some(*c) != joker)
+                .map(|c| {
+                    let mut replaced: Vec<char> = cards.chars().collect();
+                    replaced[pos] = c;
+                    Self::brute_force(&replaced.into_iter().collect::<String>(), ruleset)
+                })
+                .max()
+                .unwrap(),
+            None => Self::new(cards, None),
         }
     }
+}
 
-    fn copy_with_replace(&self, pos: usize, replace: u8) -> Self {
-        let mut cards = self.cards.clone();
-        let bid = self.bid;
-        cards[pos] = replace;
+#[derive(Debug, Clone)]
+struct Hand {
+    cards: String,
+    hand_type: HandType,
+    strengths: [u8; 5],
+    bid: u64,
+}
 
-        let mut cards_count_map: HashMap<u8, usize> = Default::default();
-        for (i, c) in cards.iter().enumerate() {
-            *cards_count_map.entry(*c).or_insert(0) += 1;
+impl Hand {
+    fn new(hand_str: &str, ruleset: &Ruleset) -> Self {
+        let (cards, bid) = hand_str.split_once(' ').unwrap();
+        let bid = bid.trim().parse::<u64>().unwrap();
+
+        let mut strengths: [u8; 5] = Default::default();
+        assert_eq!(cards.chars().count(), strengths.len(), "{}", hand_str);
+        for (i, c) in cards.chars().enumerate() {
+            strengths[i] = ruleset.strength(c);
         }
 
-        let mut card_counts: Vec<CardCount> = cards_count_map
-            .iter()
-            .map(|(card, count)| CardCount {
-                card: *card,
-                count: *count,
-            })
-            .collect();
-        card_counts.sort();
-
         Self {
-            cards,
-            card_counts,
+            cards: cards.to_string(),
+            hand_type: HandType::new(cards, ruleset.joker),
+            strengths,
             bid,
         }
     }
+}
 
-    fn card_char_to_int(c: char) -> u8 {
-        match c {
-            '2'..='9' => c.to_digit(10).unwrap() as u8,
-            'T' => 10,
-            'Q' => 11,
-            'K' => 12,
-            'A' => 13,
-            'J' => 1,
-            _ => panic!(),
-        }
-    }
-
-    fn is_five_of_a_kind(&self) -> bool {
-        self.card_counts.len() == 1
-    }
-
-    fn is_four_of_a_kind(&self) -> bool {
-        self.card_counts.len() == 2 && self.card_counts[0].count == 4
-    }
-
-    fn is_full_house(&self) -> bool {
-        self.card_counts.len() == 2
-            && self.card_counts[0].count == 3
-            && self.card_counts[1].count == 2
-    }
-
-    fn is_three_of_a_kind(&self) -> bool {
-        self.card_counts.len() == 3
-            && self.card_counts[0].count == 3
-            && self.card_counts[1].count == 1
-            && self.card_counts[2].count == 1
-    }
-
-    fn is_two_pair(&self) -> bool {
-        self.card_counts.len() == 3
-            && self.card_counts[0].count == 2
-            && self.card_counts[1].count == 2
-            && self.card_counts[2].count == 1
-    }
-
-    fn is_one_pair(&self) -> bool {
-        self.card_counts.len() == 4
-            && self.card_counts[0].count == 2
-            && self.card_counts[1].count == 1
-            && self.card_counts[2].count == 1
-            && self.card_counts[3].count == 1
-    }
-
-    fn is_high_card(&self) -> bool {
-        self.card_counts.len() == 5
-    }
-
-    fn hand_value(&self) -> u64 {
-        let v: u64 = if self.is_high_card() {
-            1
-        } else if self.is_one_pair() {
-            2
-        } else if self.is_two_pair() {
-            3
-        } else if self.is_three_of_a_kind() {
-            4
-        } else if self.is_full_house() {
-            5
-        } else if self.is_four_of_a_kind() {
-            6
-        } else if self.is_five_of_a_kind() {
-            7
-        } else {
-            dbg!(&self);
-            panic!()
-        };
-        v << (5 * 8)
-    }
-
-    fn card_value(&self) -> u64 {
-        let n = 0
-            + ((self.cards[0] as u64) << (4 * 8))
-            + ((self.cards[1] as u64) << (3 * 8))
-            + ((self.cards[2] as u64) << (2 * 8))
-            + ((self.cards[3] as u64) << (1 * 8))
-            + ((self.cards[4] as u64) << (0 * 8));
-
-        n
-    }
-
-    fn joker_value(&self) -> u64 {
-        self.joker_expand()
-            .iter()
-            .map(|hand| hand.hand_value() + self.card_value())
-            .max()
-            .unwrap()
+// Hands rank by type, then card by card from the first.
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.hand_type, self.strengths).cmp(&(other.hand_type, other.strengths))
     }
+}
 
-    fn joker_expand_pos(hand: &Hand, pos: usize) -> Vec<Hand> {
-        (2..=13)
-            .map(|c| hand.copy_with_replace(pos, c as u8))
-            .collect()
+impl PartialOrd for Hand {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn joker_expand_recurse(hands: Vec<Hand>) -> Vec<Hand> {
-        let mut ret: Vec<Hand> = Default::default();
-
-        for hand in hands.iter() {
-            let pos = hand.cards.iter().position(|c| *c == 1);
-            let mut new_hands = match pos {
-                Some(x) => Self::joker_expand_recurse(Self::joker_expand_pos(hand, x)),
-                None => vec![hand.clone()],
-            };
-            ret.append(&mut new_hands);
-        }
-
-        ret
+impl PartialEq for Hand {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
 
-    fn joker_expand(&self) -> Vec<Hand> {
-        let ret: Vec<Hand> = Self::joker_expand_recurse(vec![self.clone()]);
+impl Eq for Hand {}
 
-        //dbg!(self, &ret);
+// Hands from weakest to strongest, so a hand's rank is its index plus one.
+fn ranked_hands(data: &str, ruleset: &Ruleset) -> Vec<Hand> {
+    let mut hands: Vec<Hand> = data.lines().map(|s| Hand::new(s, ruleset)).collect();
+    hands.sort();
+    hands
+}
 
-        ret
-    }
+fn total_winnings(hands: &[Hand]) -> usize {
+    hands
+        .iter()
+        .enumerate()
+        .map(|(rank, hand)| (rank + 1) * hand.bid as usize)
+        .sum::<usize>()
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
-
-    let mut hands: Vec<Hand> = data.lines().map(Hand::new).collect();
-
-    hands.sort_by_cached_key(|hand| hand.joker_value());
+    let ranked = args[2..].iter().any(|a| a == "--ranked");
+    let order = args
+        .iter()
+        .position(|a| a == "--order")
+        .map(|i| &args[i + 1]);
+    let joker = args
+        .iter()
+        .position(|a| a == "--joker")
+        .map(|i| args[i + 1].chars().next().unwrap());
+    let data = read_to_string(filename).unwrap();
+
+    let rulesets = match order {
+        Some(order) => vec![Ruleset::new(order, joker)],
+        None => vec![Ruleset::standard(), Ruleset::jokers()],
+    };
+
+    for ruleset in rulesets.iter() {
+        let hands = ranked_hands(&data, ruleset);
+        if ranked {
+            for (rank, hand) in hands.iter().enumerate() {
+                println!(
+                    "{:>5} {} {:<12} bid {:>4} winnings {}",
+                    rank + 1,
+                    hand.cards,
+                    format!("{:?}", hand.hand_type),
+                    hand.bid,
+                    (rank + 1) * hand.bid as usize
+                );
+            }
+        }
+        println!("{}", total_winnings(&hands));
+    }
+}
 
-    //dbg!(&hands);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joker_hand_type_matches_brute_force() {
+        // Every hand of jokers and four other cards covers all joker counts.
+        let ruleset = Ruleset::jokers();
+        let alphabet: Vec<char> = "J2345".chars().collect();
+        for i in 0..alphabet.len().pow(5) {
+            let cards: String = (0..5)
+                .map(|pos| alphabet[i / alphabet.len().pow(pos) % alphabet.len()])
+                .collect();
+            assert_eq!(
+                HandType::new(&cards, ruleset.joker),
+                HandType::brute_force(&cards, &ruleset),
+                "{}",
+                cards
+            );
+        }
+    }
 
-    println!(
-        "{}",
-        hands
-            .iter()
-            .enumerate()
-            .map(|(rank, hand)| (rank + 1) * hand.bid as usize)
-            .sum::<usize>()
-    );
+    #[test]
+    fn example() {
+        let data = include_str!("test.txt");
+        for (ruleset, winnings) in [(Ruleset::standard(), 6440), (Ruleset::jokers(), 5905)] {
+            for hand in data.lines().map(|s| Hand::new(s, &ruleset)) {
+                assert_eq!(hand.hand_type, HandType::brute_force(&hand.cards, &ruleset));
+            }
+            assert_eq!(total_winnings(&ranked_hands(data, &ruleset)), winnings);
+        }
+    }
 }
@@ -1,140 +1,468 @@
 use regex::Regex;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::process;
 
-type Node = [char; 3];
-
-fn to_node(s: &str) -> (Node, [Node; 2]) {
-    let re = Regex::new(r"(?P<node>...) = \((?P<out1>...), (?P<out2>...)\)").unwrap();
-    let caps = re.captures(s).unwrap();
-
-    let node: Node = caps
-        .name("node")
-        .unwrap()
-        .as_str()
-        .chars()
-        .collect::<Vec<char>>()
-        .try_into()
-        .unwrap();
-    let out1: Node = caps
-        .name("out1")
-        .unwrap()
-        .as_str()
-        .chars()
-        .collect::<Vec<char>>()
-        .try_into()
-        .unwrap();
-    let out2: Node = caps
-        .name("out2")
-        .unwrap()
-        .as_str()
-        .chars()
-        .collect::<Vec<char>>()
-        .try_into()
-        .unwrap();
-
-    (node, [out1, out2])
+// Index of a node name in Network::names.
+type Node = usize;
+
+#[derive(Debug)]
+enum NetworkError {
+    Malformed(String),
+    NoDirections,
+    BadDirection(char),
+    DuplicateNode(String),
+    UndefinedNode {
+        node: String,
+        referenced_by: String,
+    },
+    BadPattern {
+        pattern: String,
+        error: regex::Error,
+    },
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
-
-    let mut lines = data.lines();
-
-    let directions: Vec<usize> = lines
-        .next()
-        .unwrap()
-        .chars()
-        .map(|c| match c {
-            'L' => 0,
-            'R' => 1,
-            _ => panic!(),
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Malformed(line) => write!(f, "malformed node: {:?}", line),
+            NetworkError::NoDirections => write!(f, "no directions on the first line"),
+            NetworkError::BadDirection(c) => write!(f, "bad direction {:?}", c),
+            NetworkError::DuplicateNode(node) => write!(f, "node {} is defined twice", node),
+            NetworkError::UndefinedNode {
+                node,
+                referenced_by,
+            } => write!(
+                f,
+                "node {} used by {} is never defined",
+                node, referenced_by
+            ),
+            NetworkError::BadPattern { pattern, error } => {
+                write!(f, "bad pattern {:?}: {}", pattern, error)
+            }
+        }
+    }
+}
+
+// Selects nodes by name, parsed from "prefix:X", "suffix:X", "regex:X" or an
+// exact name.
+enum Pattern {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn new(s: &str) -> Result<Self, NetworkError> {
+        match s.split_once(':') {
+            Some(("prefix", p)) => Ok(Pattern::Prefix(p.to_string())),
+            Some(("suffix", p)) => Ok(Pattern::Suffix(p.to_string())),
+            Some(("regex", p)) => {
+                Regex::new(p)
+                    .map(Pattern::Regex)
+                    .map_err(|error| NetworkError::BadPattern {
+                        pattern: s.to_string(),
+                        error,
+                    })
+            }
+            _ => Ok(Pattern::Exact(s.to_string())),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Pattern::Exact(p) => name == p,
+            Pattern::Prefix(p) => name.starts_with(p.as_str()),
+            Pattern::Suffix(p) => name.ends_with(p.as_str()),
+            Pattern::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+struct Walk {
+    steps: usize,
+    visited: Option<Vec<Node>>,
+}
+
+struct Network {
+    directions: Vec<usize>,
+    names: Vec<String>,
+    nodes: Vec<[Node; 2]>,
+}
+
+impl Network {
+    fn new(data: &str) -> Result<Self, NetworkError> {
+        let mut lines = data.lines();
+
+        let directions: Vec<usize> = lines
+            .next()
+            .filter(|line| !line.is_empty())
+            .ok_or(NetworkError::NoDirections)?
+            .chars()
+            .map(|c| match c {
+                'L' => Ok(0),
+                'R' => Ok(1),
+                _ => Err(NetworkError::BadDirection(c)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        lines.next();
+
+        let re = Regex::new(r"^(?P<node>\w+) = \((?P<out1>\w+), (?P<out2>\w+)\)$").unwrap();
+        let mut definitions: Vec<(&str, [&str; 2])> = Vec::new();
+        let mut ids: HashMap<&str, Node> = HashMap::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let caps = re
+                .captures(line)
+                .ok_or_else(|| NetworkError::Malformed(line.to_string()))?;
+            let node = caps.name("node").unwrap().as_str();
+            let out1 = caps.name("out1").unwrap().as_str();
+            let out2 = caps.name("out2").unwrap().as_str();
+            if ids.insert(node, definitions.len()).is_some() {
+                return Err(NetworkError::DuplicateNode(node.to_string()));
+            }
+            definitions.push((node, [out1, out2]));
+        }
+
+        let id = |name: &str, referenced_by: &str| {
+            ids.get(name)
+                .copied()
+                .ok_or_else(|| NetworkError::UndefinedNode {
+                    node: name.to_string(),
+                    referenced_by: referenced_by.to_string(),
+                })
+        };
+        let nodes: Vec<[Node; 2]> = definitions
+            .iter()
+            .map(|(node, [out1, out2])| Ok([id(out1, node)?, id(out2, node)?]))
+            .collect::<Result<_, _>>()?;
+        let names: Vec<String> = definitions
+            .iter()
+            .map(|(node, _)| node.to_string())
+            .collect();
+
+        Ok(Self {
+            directions,
+            names,
+            nodes,
         })
-        .collect();
+    }
 
-    lines.next();
+    fn find(&self, pattern: &Pattern) -> Vec<Node> {
+        (0..self.names.len())
+            .filter(|node| pattern.matches(&self.names[*node]))
+            .collect()
+    }
 
-    let nodes: HashMap<Node, [Node; 2]> = lines.map(to_node).collect();
+    // Follows the directions from start until is_goal matches a node, or
+    // returns None if the walk starts repeating without reaching a goal.
+    fn walk<F>(&self, start: Node, is_goal: F, record: bool) -> Option<Walk>
+    where
+        F: Fn(Node) -> bool,
+    {
+        let mut node = start;
+        let mut steps = 0;
+        let mut visited: Vec<Node> = Vec::new();
+        let mut seen: HashSet<(Node, usize)> = HashSet::new();
 
-    let mut positions: Vec<Node> = nodes
-        .keys()
-        .filter(|node| node[2] == 'A')
-        .map(|node| *node)
-        .collect();
+        loop {
+            if record {
+                visited.push(node);
+            }
+            if is_goal(node) {
+                return Some(Walk {
+                    steps,
+                    visited: record.then_some(visited),
+                });
+            }
+            if !seen.insert((node, steps % self.directions.len())) {
+                return None;
+            }
+            node = self.nodes[node][self.directions[steps % self.directions.len()]];
+            steps += 1;
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SyncError {
+    NoStartNodes,
+    Overflow,
+}
 
-    let mut count: usize = 0;
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::NoStartNodes => write!(f, "no start nodes match"),
+            SyncError::Overflow => write!(f, "step count overflows"),
+        }
+    }
+}
 
-    let mut starts: Vec<usize> = Vec::new();
-    let mut loop_lengths: Vec<usize> = Vec::new();
-    let mut z_offsets_in_loop: Vec<usize> = Vec::new();
+// Returns (g, x, y) with a * x + b * y == g == gcd(a, b).
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
 
-    for start_position in positions.iter() {
-        let mut node = start_position;
-        let mut count = 0;
-        let mut order: Vec<Node> = Vec::new();
+// Solves x = a1 (mod m1), x = a2 (mod m2) for moduli that need not be
+// coprime, returning x modulo lcm(m1, m2), or None if there is no solution.
+// Expects 0 <= a1 < m1 and 0 <= a2 < m2.
+fn crt((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Result<Option<(i128, i128)>, SyncError> {
+    let (g, p, _q) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return Ok(None);
+    }
+    let lcm = (m1 / g).checked_mul(m2).ok_or(SyncError::Overflow)?;
+    // p * m1 = g (mod m2), so stepping from a1 by multiples of m1 closes the
+    // gap. Both factors are reduced below m2 / g before multiplying.
+    let m = m2 / g;
+    let k = ((a2 - a1) / g)
+        .rem_euclid(m)
+        .checked_mul(p.rem_euclid(m))
+        .ok_or(SyncError::Overflow)?
+        .rem_euclid(m);
+    // a1 + m1 * k < m1 + m1 * (m - 1) = lcm, so this can't overflow.
+    Ok(Some((a1 + m1 * k, lcm)))
+}
+
+// A walk's goal steps: every step in hits reaches a goal, and from step
+// prefix on the walk repeats every period steps.
+struct Cycle {
+    prefix: usize,
+    period: usize,
+    hits: Vec<usize>,
+}
+
+impl Cycle {
+    fn is_hit(&self, step: usize) -> bool {
+        let step = if step >= self.prefix + self.period {
+            self.prefix + (step - self.prefix) % self.period
+        } else {
+            step
+        };
+        self.hits.contains(&step)
+    }
+}
+
+impl Network {
+    fn cycle<F>(&self, start: &Node, is_goal: F) -> Cycle
+    where
+        F: Fn(&Node) -> bool,
+    {
+        let mut node = start;
+        let mut steps = 0;
+        let mut hits: Vec<usize> = Vec::new();
         let mut seen: HashMap<(Node, usize), usize> = HashMap::new();
-        let mut z_position = 0;
 
-        loop {
-            if node[2] == 'Z' {
-                z_position = count;
+        while let Entry::Vacant(e) = seen.entry((*node, steps % self.directions.len())) {
+            e.insert(steps);
+            if is_goal(node) {
+                hits.push(steps);
             }
-            let entry = seen.entry((*node, count % directions.len()));
-            match entry {
-                Entry::Occupied(x) => {
-                    break;
-                }
-                Entry::Vacant(x) => {
-                    x.insert(count);
-                }
+            node = &self.nodes[*node][self.directions[steps % self.directions.len()]];
+            steps += 1;
+        }
+
+        let prefix = seen[&(*node, steps % self.directions.len())];
+        Cycle {
+            prefix,
+            period: steps - prefix,
+            hits,
+        }
+    }
+}
+
+// The first step at which every walk is at a goal, or None if there is none.
+fn synchronize(cycles: &[Cycle]) -> Result<Option<u128>, SyncError> {
+    let prefix = cycles
+        .iter()
+        .map(|c| c.prefix)
+        .max()
+        .ok_or(SyncError::NoStartNodes)?;
+
+    // Before the longest prefix, check the first walk's hits directly.
+    let first = &cycles[0];
+    let early = (0..prefix)
+        .filter(|step| first.is_hit(*step))
+        .find(|step| cycles.iter().all(|c| c.is_hit(*step)));
+    if let Some(step) = early {
+        return Ok(Some(step as u128));
+    }
+
+    // After it every walk is periodic, so combine each walk's residues.
+    let mut solutions: Vec<(i128, i128)> = vec![(0, 1)];
+    for cycle in cycles.iter() {
+        let residues: Vec<(i128, i128)> = cycle
+            .hits
+            .iter()
+            .filter(|hit| **hit >= cycle.prefix)
+            .map(|hit| ((hit % cycle.period) as i128, cycle.period as i128))
+            .collect();
+        let mut combined: Vec<(i128, i128)> = Vec::new();
+        for s in solutions.iter() {
+            for r in residues.iter() {
+                combined.extend(crt(*s, *r)?);
             }
-            order.push(*node);
-            node = &nodes[node][directions[count % directions.len()]];
-            count += 1;
         }
+        combined.sort();
+        combined.dedup();
+        solutions = combined;
+    }
 
-        let loop_start = seen[&(*node, count % directions.len())];
+    let mut first: Option<u128> = None;
+    for (a, m) in solutions.iter() {
+        // Smallest step >= prefix that is a modulo m.
+        let prefix = prefix as i128;
+        let step = (a - prefix)
+            .rem_euclid(*m)
+            .checked_add(prefix)
+            .ok_or(SyncError::Overflow)? as u128;
+        first = Some(first.map_or(step, |first| first.min(step)));
+    }
+    Ok(first)
+}
 
-        dbg!(count, z_position);
-        starts.push(loop_start);
-        loop_lengths.push(count - loop_start);
-        z_offsets_in_loop.push(z_position - loop_start);
-        //dbg!(loop_start, &order);
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = args[1].as_str();
+    let print_path = args[2..].iter().any(|a| a == "--path");
+    let ghost_start = args
+        .iter()
+        .position(|a| a == "--start")
+        .map_or("suffix:A", |i| args[i + 1].as_str());
+    let ghost_end = args
+        .iter()
+        .position(|a| a == "--end")
+        .map_or("suffix:Z", |i| args[i + 1].as_str());
+    let data = read_to_string(filename).unwrap();
+
+    let (network, ghost_start, ghost_end) = Network::new(&data)
+        .and_then(|network| {
+            Ok((
+                network,
+                Pattern::new(ghost_start)?,
+                Pattern::new(ghost_end)?,
+            ))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1)
+        });
 
-        assert!(z_position == count - loop_start);
+    let start = Pattern::Exact("AAA".to_string());
+    let end = Pattern::Exact("ZZZ".to_string());
+    let is_end: Vec<bool> = network.names.iter().map(|n| end.matches(n)).collect();
+    match network.find(&start).first() {
+        Some(start) => match network.walk(*start, |node| is_end[node], print_path) {
+            Some(walk) => {
+                if let Some(visited) = walk.visited {
+                    for node in visited {
+                        println!("{}", network.names[node]);
+                    }
+                }
+                println!("{}", walk.steps);
+            }
+            None => println!("AAA never reaches ZZZ"),
+        },
+        None => println!("no AAA node"),
     }
-    dbg!(&starts, &loop_lengths, &z_offsets_in_loop);
 
-    let mut counts: Vec<usize> = starts
+    let is_ghost_end: Vec<bool> = network.names.iter().map(|n| ghost_end.matches(n)).collect();
+    let cycles: Vec<Cycle> = network
+        .find(&ghost_start)
         .iter()
-        .zip(z_offsets_in_loop.iter())
-        .map(|(a, b)| a + b)
+        .map(|node| network.cycle(node, |node| is_ghost_end[*node]))
         .collect();
+    match synchronize(&cycles) {
+        Ok(Some(steps)) => println!("{}", steps),
+        Ok(None) => println!("ghosts are never all on end nodes at once"),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1)
+        }
+    }
+}
 
-    //let mut counts: Vec<usize> = vec![counts2[0]];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    while counts.iter().min() != counts.iter().max() {
-        let min = counts
+    fn ghost_steps(data: &str) -> Result<Option<u128>, SyncError> {
+        let network = Network::new(data).unwrap();
+        let end = Pattern::new("suffix:Z").unwrap();
+        let is_end: Vec<bool> = network.names.iter().map(|n| end.matches(n)).collect();
+        let cycles: Vec<Cycle> = network
+            .find(&Pattern::new("suffix:A").unwrap())
             .iter()
-            .enumerate()
-            .min_by_key(|(_, count)| *count)
-            .unwrap()
-            .0;
-        let max = counts
-            .iter()
-            .enumerate()
-            .max_by_key(|(_, count)| *count)
-            .unwrap()
-            .0;
+            .map(|node| network.cycle(node, |node| is_end[*node]))
+            .collect();
+        synchronize(&cycles)
+    }
+
+    #[test]
+    fn synchronize_examples() {
+        assert!(matches!(
+            ghost_steps(include_str!("test2.txt")),
+            Ok(Some(6))
+        ));
+        assert!(matches!(
+            ghost_steps(include_str!("test3.txt")),
+            Ok(Some(11))
+        ));
+    }
+
+    #[test]
+    fn synchronize_never() {
+        // One ghost is on an end node at odd steps, the other at even steps.
+        let data =
+            "L\n\n1A = (1Z, 1Z)\n1Z = (1A, 1A)\n2A = (2B, 2B)\n2B = (2Z, 2Z)\n2Z = (2B, 2B)\n";
+        assert!(matches!(ghost_steps(data), Ok(None)));
+    }
 
-        assert!(counts[min] < counts[max]);
-        //dbg!(min, counts[min], max, counts[max]);
-        counts[min] += loop_lengths[min];
+    #[test]
+    fn synchronize_no_start_nodes() {
+        let data = "L\n\n1B = (1Z, 1Z)\n1Z = (1B, 1B)\n";
+        assert!(matches!(ghost_steps(data), Err(SyncError::NoStartNodes)));
     }
 
-    dbg!(&counts);
+    #[test]
+    fn crt_matches_brute_force() {
+        for m1 in 1..13 {
+            for m2 in 1..13 {
+                for a1 in 0..m1 {
+                    for a2 in 0..m2 {
+                        let lcm = (1..=m1 * m2).find(|l| l % m1 == 0 && l % m2 == 0).unwrap();
+                        let expected = (0..lcm).find(|x| x % m1 == a1 && x % m2 == a2);
+                        assert_eq!(
+                            crt((a1, m1), (a2, m2)).unwrap(),
+                            expected.map(|x| (x, lcm)),
+                            "{} mod {}, {} mod {}",
+                            a1,
+                            m1,
+                            a2,
+                            m2
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crt_overflow() {
+        let big = i128::MAX / 3;
+        assert!(matches!(
+            crt((1, big), (2, big - 1)),
+            Err(SyncError::Overflow)
+        ));
+    }
 }
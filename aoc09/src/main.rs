@@ -1,62 +1,366 @@
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::process;
+
+#[derive(Debug)]
+enum SequenceError {
+    BadNumber(String),
+    Overflow,
+    NoZeroRow,
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SequenceError::BadNumber(word) => write!(f, "bad number {:?}", word),
+            SequenceError::Overflow => write!(f, "arithmetic overflow"),
+            SequenceError::NoZeroRow => write!(f, "differences never reach all zeros"),
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// A sequence's values as a polynomial in the index n, counting from 0 at the
+// first value: f(n) is the sum of differences[k] * C(n, k), where
+// differences[k] is the first value in row k of the difference table.
+#[derive(Debug)]
+struct Polynomial {
+    differences: Vec<i128>,
+}
+
+impl Polynomial {
+    fn degree(&self) -> usize {
+        self.differences.len() - 1
+    }
+
+    // Also works for n before the first value, where C(n, k) is negative for
+    // odd k.
+    fn value_at(&self, n: i128) -> Result<i128, SequenceError> {
+        let mut binomial: i128 = 1;
+        let mut value: i128 = 0;
+        for (k, difference) in self.differences.iter().enumerate() {
+            if k > 0 {
+                // C(n, k) = C(n, k - 1) * (n - k + 1) / k, always exact.
+                let k = k as i128;
+                binomial = n
+                    .checked_sub(k - 1)
+                    .and_then(|m| binomial.checked_mul(m))
+                    .ok_or(SequenceError::Overflow)?
+                    / k;
+            }
+            value = difference
+                .checked_mul(binomial)
+                .and_then(|term| value.checked_add(term))
+                .ok_or(SequenceError::Overflow)?;
+        }
+        Ok(value)
+    }
+
+    // Coefficients of n^0, n^1, ... over a common denominator of degree!.
+    fn coefficients(&self) -> Result<(Vec<i128>, i128), SequenceError> {
+        let overflow = || SequenceError::Overflow;
+        let denominator = (1..=self.degree() as i128)
+            .try_fold(1i128, |f, k| f.checked_mul(k))
+            .ok_or_else(overflow)?;
+
+        let mut numerators: Vec<i128> = vec![0; self.differences.len()];
+        // n * (n - 1) * ... * (n - k + 1) and k!
+        let mut falling: Vec<i128> = vec![1];
+        let mut factorial: i128 = 1;
+        for (k, difference) in self.differences.iter().enumerate() {
+            if k > 0 {
+                factorial *= k as i128;
+                let mut next: Vec<i128> = vec![0; falling.len() + 1];
+                for (i, c) in falling.iter().enumerate() {
+                    next[i + 1] = next[i + 1].checked_add(*c).ok_or_else(overflow)?;
+                    next[i] = c
+                        .checked_mul(k as i128 - 1)
+                        .and_then(|m| next[i].checked_sub(m))
+                        .ok_or_else(overflow)?;
+                }
+                falling = next;
+            }
+            let scale = difference
+                .checked_mul(denominator / factorial)
+                .ok_or_else(overflow)?;
+            for (i, c) in falling.iter().enumerate() {
+                numerators[i] = c
+                    .checked_mul(scale)
+                    .and_then(|m| numerators[i].checked_add(m))
+                    .ok_or_else(overflow)?;
+            }
+        }
+        Ok((numerators, denominator))
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (numerators, denominator) = match self.coefficients() {
+            Ok(coefficients) => coefficients,
+            Err(e) => return write!(f, "{}", e),
+        };
+        let mut terms: Vec<String> = Vec::new();
+        for (power, numerator) in numerators.iter().enumerate().rev() {
+            if *numerator == 0 {
+                continue;
+            }
+            let divisor = gcd(*numerator, denominator);
+            let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+            let coefficient = match (numerator.abs(), denominator, power) {
+                (1, 1, 1..) => "".to_string(),
+                (n, 1, _) => n.to_string(),
+                (n, d, 0) => format!("{}/{}", n, d),
+                (n, d, _) => format!("{}/{} ", n, d),
+            };
+            let sign = match (terms.is_empty(), numerator < 0) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            let term = match power {
+                0 => coefficient,
+                1 => format!("{}n", coefficient),
+                _ => format!("{}n^{}", coefficient, power),
+            };
+            terms.push(format!("{}{}", sign, term));
+        }
+        if terms.is_empty() {
+            terms.push("0".to_string());
+        }
+        write!(f, "degree {}: f(n) = {}", self.degree(), terms.concat())
+    }
+}
 
 #[derive(Debug)]
 struct Sequence {
-    values: Vec<i64>,
+    values: Vec<i128>,
 }
 
 impl Sequence {
-    fn new(line: &str) -> Self {
-        let values: Vec<i64> = line
+    fn new(line: &str) -> Result<Self, SequenceError> {
+        let values: Vec<i128> = line
             .split_whitespace()
-            .map(|s| s.parse::<i64>().unwrap())
-            .collect();
-        Self { values }
+            .map(|s| {
+                s.parse::<i128>()
+                    .map_err(|_| SequenceError::BadNumber(s.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { values })
     }
 
-    fn diff(&self) -> Self {
-        let values: Vec<i64> = self
+    fn diff(&self) -> Result<Self, SequenceError> {
+        let values: Vec<i128> = self
             .values
             .windows(2)
-            .map(|v: &[i64]| v[1] - v[0])
-            .collect();
-        Self { values }
+            .map(|v: &[i128]| v[1].checked_sub(v[0]).ok_or(SequenceError::Overflow))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { values })
     }
 
     fn all_zero(&self) -> bool {
         self.values.iter().all(|v| *v == 0)
     }
 
-    fn extrapolate(&self) -> i64 {
-        match self.all_zero() {
-            true => 0,
-            false => self.values.last().unwrap() + self.diff().extrapolate(),
+    // The rows of the difference table, starting with this sequence and
+    // ending with the first row of all zeros. A single value is taken to be
+    // constant, but otherwise running out of differences is an error.
+    fn differences(&self) -> Result<Vec<Sequence>, SequenceError> {
+        let mut rows: Vec<Sequence> = vec![Self {
+            values: self.values.clone(),
+        }];
+        while !rows.last().unwrap().all_zero() {
+            let next = rows.last().unwrap().diff()?;
+            if next.values.is_empty() {
+                if self.values.len() != 1 {
+                    return Err(SequenceError::NoZeroRow);
+                }
+                rows.push(Self { values: vec![0] });
+                break;
+            }
+            rows.push(next);
         }
+        Ok(rows)
     }
 
-    fn extrapolate_previous(&self) -> i64 {
-        match self.all_zero() {
-            true => 0,
-            false => self.values.first().unwrap() - self.diff().extrapolate_previous(),
+    fn fit(&self) -> Result<Polynomial, SequenceError> {
+        let rows = self.differences()?;
+        let differences: Vec<i128> = rows[..rows.len() - 1]
+            .iter()
+            .map(|row| row.values[0])
+            .collect();
+        match differences.is_empty() {
+            true => Ok(Polynomial {
+                differences: vec![0],
+            }),
+            false => Ok(Polynomial { differences }),
         }
     }
+
+    fn extrapolate(&self) -> Result<i128, SequenceError> {
+        self.differences()?
+            .iter()
+            .rev()
+            .try_fold(0i128, |below, row| {
+                row.values
+                    .last()
+                    .unwrap()
+                    .checked_add(below)
+                    .ok_or(SequenceError::Overflow)
+            })
+    }
+
+    fn extrapolate_previous(&self) -> Result<i128, SequenceError> {
+        self.differences()?
+            .iter()
+            .rev()
+            .try_fold(0i128, |below, row| {
+                row.values
+                    .first()
+                    .unwrap()
+                    .checked_sub(below)
+                    .ok_or(SequenceError::Overflow)
+            })
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
+    let print_fit = args[2..].iter().any(|a| a == "--fit");
+    let at: Option<i128> = args
+        .iter()
+        .position(|a| a == "--at")
+        .map(|i| args[i + 1].parse().unwrap());
+    let data = read_to_string(filename).unwrap();
 
-    let lines = data.lines();
+    let mut next_sum: i128 = 0;
+    let mut previous_sum: i128 = 0;
+    for (i, line) in data
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let result = Sequence::new(line).and_then(|seq| {
+            if print_fit || at.is_some() {
+                let polynomial = seq.fit()?;
+                if print_fit {
+                    println!("{}", polynomial);
+                }
+                if let Some(n) = at {
+                    println!("f({}) = {}", n, polynomial.value_at(n)?);
+                }
+            }
+            let next = next_sum.checked_add(seq.extrapolate()?);
+            let previous = previous_sum.checked_add(seq.extrapolate_previous()?);
+            Ok((
+                next.ok_or(SequenceError::Overflow)?,
+                previous.ok_or(SequenceError::Overflow)?,
+            ))
+        });
+        match result {
+            Ok((next, previous)) => {
+                next_sum = next;
+                previous_sum = previous;
+            }
+            Err(e) => {
+                eprintln!("line {}: {}", i + 1, e);
+                process::exit(1);
+            }
+        }
+    }
 
-    let sequences: Vec<Sequence> = lines.map(Sequence::new).collect();
+    println!("{}", next_sum);
+    println!("{}", previous_sum);
+}
 
-    println!(
-        "{}",
-        sequences
-            .iter()
-            .map(|seq| seq.extrapolate_previous())
-            .sum::<i64>()
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequences() -> Vec<Sequence> {
+        include_str!("test.txt")
+            .lines()
+            .map(|line| Sequence::new(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fit_matches_values_and_extrapolation() {
+        for seq in sequences() {
+            let polynomial = seq.fit().unwrap();
+            let (numerators, denominator) = polynomial.coefficients().unwrap();
+            for (n, value) in seq.values.iter().enumerate() {
+                let n = n as i128;
+                assert_eq!(polynomial.value_at(n).unwrap(), *value);
+                let sum: i128 = numerators.iter().rev().fold(0, |sum, c| sum * n + c);
+                assert_eq!(sum, value * denominator);
+            }
+            let len = seq.values.len() as i128;
+            assert_eq!(
+                polynomial.value_at(len).unwrap(),
+                seq.extrapolate().unwrap()
+            );
+            assert_eq!(
+                polynomial.value_at(-1).unwrap(),
+                seq.extrapolate_previous().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn fit_example() {
+        let polynomial = sequences()[1].fit().unwrap();
+        assert_eq!(polynomial.value_at(-5).unwrap(), 6);
+        assert_eq!(
+            polynomial.to_string(),
+            "degree 2: f(n) = 1/2 n^2 + 3/2 n + 1"
+        );
+    }
+
+    #[test]
+    fn fit_cubes() {
+        let polynomial = Sequence::new("0 1 8 27 64").unwrap().fit().unwrap();
+        assert_eq!(polynomial.to_string(), "degree 3: f(n) = n^3");
+        assert_eq!(polynomial.value_at(-3).unwrap(), -27);
+    }
+
+    #[test]
+    fn single_value_is_constant() {
+        let seq = Sequence::new("5").unwrap();
+        assert_eq!(seq.extrapolate().unwrap(), 5);
+        assert_eq!(seq.extrapolate_previous().unwrap(), 5);
+    }
+
+    #[test]
+    fn no_zero_row() {
+        let seq = Sequence::new("1 2 4").unwrap();
+        assert!(matches!(seq.extrapolate(), Err(SequenceError::NoZeroRow)));
+        assert!(matches!(
+            seq.extrapolate_previous(),
+            Err(SequenceError::NoZeroRow)
+        ));
+    }
+
+    #[test]
+    fn overflow() {
+        // The differences themselves overflow.
+        let seq = Sequence::new(&format!("0 {} {}", i128::MAX, -i128::MAX)).unwrap();
+        assert!(matches!(seq.differences(), Err(SequenceError::Overflow)));
+
+        // The differences fit, but the next value doesn't.
+        let max = i128::MAX;
+        let seq = Sequence::new(&format!("{} {} {}", max - 2, max - 1, max)).unwrap();
+        assert!(matches!(seq.extrapolate(), Err(SequenceError::Overflow)));
+        assert_eq!(seq.extrapolate_previous().unwrap(), max - 3);
+    }
 }
@@ -1,6 +1,8 @@
 use std::{env, ops};
 use std::cmp::Ordering;
+use std::fmt;
 use std::fs::read_to_string;
+use std::process;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 struct Pos {
@@ -45,12 +47,72 @@ impl ops::Add<&Dir> for &Pos {
 }
 
 
+#[derive(Debug)]
+enum PipeError {
+    UnknownGlyph { c: char, pos: Pos },
+    RaggedRow { row: usize, len: usize, expected: usize },
+    NoStart,
+    ExtraStart(Pos),
+    AmbiguousStart(Vec<char>),
+    OpenPipe { shape: char, end: OpenEnd, glyph: char, next_glyph: Option<char> },
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipeError::UnknownGlyph { c, pos } => {
+                write!(f, "unknown glyph {:?} at row {} col {}", c, pos.row, pos.col)
+            }
+            PipeError::RaggedRow { row, len, expected } => {
+                write!(f, "row {} has {} tiles, expected {}", row, len, expected)
+            }
+            PipeError::NoStart => write!(f, "no start tile S"),
+            PipeError::ExtraStart(pos) => {
+                write!(f, "second start tile S at row {} col {}", pos.row, pos.col)
+            }
+            PipeError::AmbiguousStart(shapes) => write!(
+                f,
+                "start tile closes a loop as any of {}",
+                shapes.iter().collect::<String>()
+            ),
+            PipeError::OpenPipe { shape, end, glyph, next_glyph } => {
+                write!(
+                    f,
+                    "no loop through S; the longest path, with S as {:?}, ends after {} steps \
+                     at {:?} on row {} col {}, which leads to ",
+                    shape, end.steps, glyph, end.pos.row, end.pos.col
+                )?;
+                match next_glyph {
+                    Some(c) => write!(
+                        f,
+                        "unconnected {:?} on row {} col {}",
+                        c, end.next.row, end.next.col
+                    ),
+                    None => write!(f, "the edge of the grid"),
+                }
+            }
+        }
+    }
+}
+
+// Where a pipe followed from the start stops connecting: the last tile on
+// the path, how many steps from the start it is, and the tile it points to.
+#[derive(Debug)]
+struct OpenEnd {
+    steps: usize,
+    pos: Pos,
+    next: Pos,
+}
+
 #[derive(Copy, Clone, Debug)]
 struct Tile {
     c: char,
 }
 
 impl Tile {
+    const PIPES: [char; 6] = ['|', '-', 'L', 'J', '7', 'F'];
+
+    // The start tile connects nowhere until it is replaced by its pipe.
     fn connected(&self) -> [Dir; 2] {
         match self.c {
             '|' => [Dir::NORTH, Dir::SOUTH],
@@ -59,28 +121,13 @@ impl Tile {
             'J' => [Dir::NORTH, Dir::WEST],
             '7' => [Dir::SOUTH, Dir::WEST],
             'F' => [Dir::EAST, Dir::SOUTH],
-            '.' => [Dir::NONE, Dir::NONE],
-            _ => panic!(),
+            '.' | 'S' => [Dir::NONE, Dir::NONE],
+            _ => panic!("unknown glyph {:?}", self.c),
         }
     }
-
-    fn from_dirs(dirs: [Dir; 2]) -> Self {
-        dbg!(&dirs);
-        let c = match dirs {
-            [Dir::NORTH, Dir::SOUTH] => '|',
-            [Dir::EAST, Dir::WEST] => '-',
-            [Dir::NORTH, Dir::EAST] => 'L',
-            [Dir::NORTH, Dir::WEST] => 'J',
-            [Dir::SOUTH, Dir::WEST] => '7',
-            [Dir::EAST, Dir::SOUTH] => 'F',
-            [Dir::NONE, Dir::NONE] => '.',
-            _ => panic!(),
-        };
-        Self { c }
-    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Grid {
     grid: Vec<Vec<Tile>>,
 
@@ -89,7 +136,7 @@ struct Grid {
 }
 
 impl Grid {
-    fn new(data: &str) -> Self {
+    fn new(data: &str) -> Result<Self, PipeError> {
         let grid: Vec<Vec<Tile>> = data
             .lines()
             .map(|s| s
@@ -98,8 +145,19 @@ impl Grid {
                 .collect::<Vec<_>>())
             .collect::<Vec<_>>();
         let rows = grid.len();
-        let cols = grid[0].len();
-        Self { grid, rows, cols }
+        let cols = grid.first().map_or(0, |row| row.len());
+        for (row, tiles) in grid.iter().enumerate() {
+            for (col, tile) in tiles.iter().enumerate() {
+                if tile.c != '.' && tile.c != 'S' && !Tile::PIPES.contains(&tile.c) {
+                    let pos = Pos { row: row as i64, col: col as i64 };
+                    return Err(PipeError::UnknownGlyph { c: tile.c, pos });
+                }
+            }
+            if tiles.len() != cols {
+                return Err(PipeError::RaggedRow { row, len: tiles.len(), expected: cols });
+            }
+        }
+        Ok(Self { grid, rows, cols })
     }
 
     fn empty_copy(&self) -> Self {
@@ -113,28 +171,15 @@ impl Grid {
         Self { grid, rows, cols }
     }
 
-    fn find_start(&self) -> Pos {
-        let row_col = self
-            .grid
-            .iter()
-            .map(|tiles| tiles
-                .iter()
-                .enumerate()
-                .find(|(_col, tile)| tile.c == 'S'))
-            .enumerate()
-            .find(|(_row, start_col)| start_col.is_some())
-            .unwrap();
-        Pos { row: row_col.0 as i64, col: row_col.1.unwrap().0 as i64 }
-    }
-
-    fn find_start_dirs(&self, start: &Pos) -> [Dir; 2] {
-        let mut dirs = Dir::DIRECTIONS
-            .iter()
-            .filter(|dir| self.follow_pipe(&start, &(start + *dir)).is_some())
-            .collect::<Vec<&Dir>>();
-        dirs.sort();
-        assert!(dirs.len() == 2);
-        [*dirs[0], *dirs[1]]
+    fn find_start(&self) -> Result<Pos, PipeError> {
+        let mut starts = (0..self.rows)
+            .flat_map(|row| (0..self.cols).map(move |col| Pos { row: row as i64, col: col as i64 }))
+            .filter(|pos| self.at(pos).unwrap().c == 'S');
+        let start = starts.next().ok_or(PipeError::NoStart)?;
+        match starts.next() {
+            Some(pos) => Err(PipeError::ExtraStart(pos)),
+            None => Ok(start),
+        }
     }
 
     fn at(&self, pos: &Pos) -> Option<Tile> {
@@ -167,42 +212,82 @@ impl Grid {
         }
     }
 
-    fn collect_pipe(&self, start: &Pos, dir: &Dir) -> Vec<Pos> {
+    // Follows the pipe from the start in dir until it comes back round,
+    // expecting the start to have been replaced by its pipe.
+    fn collect_pipe(&self, start: &Pos, dir: &Dir) -> Result<Vec<Pos>, OpenEnd> {
         let mut pipe_positions: Vec<Pos> = Vec::new();
         pipe_positions.push(*start);
         let mut pos = start + dir;
         let mut prev_pos = *start;
         loop {
-            let next_dir = self.follow_pipe(&prev_pos, &pos);
-            if !next_dir.is_some() {
-                panic!();
+            let next_dir = match self.follow_pipe(&prev_pos, &pos) {
+                Some(next_dir) => next_dir,
+                None => {
+                    return Err(OpenEnd { steps: pipe_positions.len() - 1, pos: prev_pos, next: pos });
+                }
+            };
+            if pos == *start {
+                return Ok(pipe_positions);
             }
             pipe_positions.push(pos);
             prev_pos = pos;
-            pos = &pos + &next_dir.unwrap();
-            if self.at(&pos).unwrap_or(Tile { c: '.' }).c == 'S' {
-                return pipe_positions;
-            }
+            pos = &pos + &next_dir;
         }
     }
 
-    fn collect_connected(&mut self, pos: &Pos) -> Vec<Pos> {
-        self.set(pos, 'C');
-        let mut connected: Vec<Pos> = Vec::new();
-        connected.push(*pos);
-        for dir in Dir::DIRECTIONS.iter() {
-            let adjacent_pos = pos + dir;
-            let adjacent_tile = self.at(&adjacent_pos);
-            if adjacent_tile.is_some() {
-                match adjacent_tile.unwrap().c {
-                    '*' => continue,
-                    'C' => continue,
-                    '.' => connected.append(&mut self.collect_connected(&adjacent_pos)),
-                    _ => panic!(),
+    // Floor tiles enclosed by the loop, found by flooding the outside on a
+    // grid at twice the resolution, where the gaps between tiles are cells
+    // too and the flood can squeeze between pipes that don't connect.
+    // Expects a grid holding only the loop's pipes, like count_enclosed.
+    fn enclosed(&self) -> Vec<Pos> {
+        // Tile (row, col) sits at (2 * row + 1, 2 * col + 1), leaving a
+        // border of gaps so the outside is reachable from the corner.
+        let rows = 2 * self.rows + 1;
+        let cols = 2 * self.cols + 1;
+        let mut blocked = vec![vec![false; cols]; rows];
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let tile = self.grid[row][col];
+                if tile.c == '.' {
+                    continue;
+                }
+                let center = Pos { row: 2 * row as i64 + 1, col: 2 * col as i64 + 1 };
+                blocked[center.row as usize][center.col as usize] = true;
+                for dir in tile.connected().iter() {
+                    let gap = &center + dir;
+                    blocked[gap.row as usize][gap.col as usize] = true;
+                }
+            }
+        }
+
+        let mut outside = vec![vec![false; cols]; rows];
+        let mut stack: Vec<Pos> = vec![Pos { row: 0, col: 0 }];
+        outside[0][0] = true;
+        while let Some(pos) = stack.pop() {
+            for dir in Dir::DIRECTIONS.iter() {
+                let next = &pos + dir;
+                if next.row < 0 || next.row as usize >= rows ||
+                    next.col < 0 || next.col as usize >= cols {
+                    continue;
+                }
+                let (row, col) = (next.row as usize, next.col as usize);
+                if !blocked[row][col] && !outside[row][col] {
+                    outside[row][col] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let mut enclosed: Vec<Pos> = Vec::new();
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let (big_row, big_col) = (2 * row + 1, 2 * col + 1);
+                if !blocked[big_row][big_col] && !outside[big_row][big_col] {
+                    enclosed.push(Pos { row: row as i64, col: col as i64 });
                 }
             }
         }
-        connected
+        enclosed
     }
 
     fn count(&self, c: char) -> usize {
@@ -213,37 +298,12 @@ impl Grid {
             .filter(|tile| tile.c == c)
             .count()
     }
-
-    fn to_string(&self) -> String {
-        self.grid
-            .iter()
-            .map(|row| row.iter().map(|tile| tile.c).collect::<String>())
-            .collect::<Vec<String>>()
-            .join("\n")
-    }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
-
-    let grid = Grid::new(data.as_str());
-
-    let start = grid.find_start();
-
-    let start_dirs = grid.find_start_dirs(&start);
-    let start_dir = &start_dirs[0];
-
-    let pipe_positions = grid.collect_pipe(&start, start_dir);
-
-    let mut grid_mark = grid.empty_copy();
-    for pos in pipe_positions {
-        grid_mark.set(&pos, grid.at(&pos).unwrap().c);
-    }
-    grid_mark.set(&start, Tile::from_dirs(start_dirs).c);
-    println!("{}", &grid_mark.to_string());
-
+// Counts floor tiles inside the loop by scanning each row and flipping
+// between outside and inside at every crossing of the loop. Expects a grid
+// holding only the loop's pipes, with the start replaced by its pipe.
+fn count_enclosed(grid_mark: &Grid) -> usize {
     let mut count = 0;
     for row in 0..grid_mark.rows {
         let mut last_corner = '.';
@@ -258,7 +318,6 @@ fn main() {
                 if c == '|' {
                     inside = !inside;
                 } else if c == 'J' {
-                    dbg!(pos, grid_mark.at(&pos).unwrap().c);
                     assert!(last_corner == 'F' || last_corner == 'L');
                     if last_corner == 'F' {
                         inside = !inside;
@@ -276,6 +335,232 @@ fn main() {
             }
         }
     }
+    count
+}
+
+#[derive(Debug)]
+struct Loop {
+    length: usize,
+    farthest_distance: usize,
+    farthest: Pos,
+    enclosed: usize,
+}
+
+// Works out the start's pipe by trying each shape in turn and keeping the
+// one that closes a loop, then follows that loop once, marking its pipes on
+// an empty grid that is also returned.
+fn solve(grid: &Grid) -> Result<(Loop, Grid), PipeError> {
+    let start = grid.find_start()?;
+
+    let mut loops: Vec<(char, Vec<Pos>)> = Vec::new();
+    let mut longest_open: Option<(char, OpenEnd)> = None;
+    let mut pipes = grid.clone();
+    for shape in Tile::PIPES {
+        pipes.set(&start, shape);
+        let start_dir = &Tile { c: shape }.connected()[0];
+        match pipes.collect_pipe(&start, start_dir) {
+            Ok(pipe_positions) => loops.push((shape, pipe_positions)),
+            Err(end) => {
+                if longest_open.as_ref().is_none_or(|(_, longest)| end.steps > longest.steps) {
+                    longest_open = Some((shape, end));
+                }
+            }
+        }
+    }
+
+    let (shape, pipe_positions) = match loops.len() {
+        0 => {
+            let (shape, end) = longest_open.unwrap();
+            pipes.set(&start, shape);
+            let glyph = pipes.at(&end.pos).unwrap().c;
+            let next_glyph = grid.at(&end.next).map(|tile| tile.c);
+            return Err(PipeError::OpenPipe { shape, end, glyph, next_glyph });
+        }
+        1 => loops.pop().unwrap(),
+        _ => return Err(PipeError::AmbiguousStart(loops.iter().map(|(shape, _)| *shape).collect())),
+    };
+
+    let mut grid_mark = grid.empty_copy();
+    for pos in pipe_positions.iter() {
+        grid_mark.set(pos, grid.at(pos).unwrap().c);
+    }
+    grid_mark.set(&start, shape);
+
+    // The loop has an even length, so the tile halfway round is farthest
+    // from the start in both directions.
+    let length = pipe_positions.len();
+    let solution = Loop {
+        length,
+        farthest_distance: length / 2,
+        farthest: pipe_positions[length / 2],
+        enclosed: count_enclosed(&grid_mark),
+    };
+    Ok((solution, grid_mark))
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_LOOP: &str = "\x1b[1;36m";
+const ANSI_INSIDE: &str = "\x1b[1;32m";
+const ANSI_OUTSIDE: &str = "\x1b[2m";
+
+// Renders the loop with box-drawing characters, leaving out any pipes that
+// aren't part of it. Enclosed tiles are shaded and the rest left blank, or
+// with color, enclosed tiles are green and the outside is dimmed.
+fn render(grid_mark: &Grid, enclosed: &[Pos], color: bool) -> String {
+    (0..grid_mark.rows)
+        .map(|row| {
+            (0..grid_mark.cols)
+                .map(|col| {
+                    let pos = Pos { row: row as i64, col: col as i64 };
+                    let (c, ansi) = match grid_mark.at(&pos).unwrap().c {
+                        '|' => ('\u{2502}', ANSI_LOOP),
+                        '-' => ('\u{2500}', ANSI_LOOP),
+                        'L' => ('\u{2514}', ANSI_LOOP),
+                        'J' => ('\u{2518}', ANSI_LOOP),
+                        '7' => ('\u{2510}', ANSI_LOOP),
+                        'F' => ('\u{250c}', ANSI_LOOP),
+                        _ if enclosed.contains(&pos) => ('\u{2592}', ANSI_INSIDE),
+                        _ if color => ('\u{00b7}', ANSI_OUTSIDE),
+                        _ => (' ', ANSI_OUTSIDE),
+                    };
+                    match color {
+                        true => format!("{}{}{}", ansi, c, ANSI_RESET),
+                        false => c.to_string(),
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = args[1].as_str();
+    let render_color = args[2..].iter().any(|a| a == "--render");
+    let render_plain = args[2..].iter().any(|a| a == "--plain");
+    let data = read_to_string(filename).unwrap();
+
+    let (solution, grid_mark) = Grid::new(data.as_str())
+        .and_then(|grid| solve(&grid))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1)
+        });
+    if render_color || render_plain {
+        println!("{}", render(&grid_mark, &grid_mark.enclosed(), render_color));
+    }
+
+    println!("loop length: {}", solution.length);
+    println!(
+        "farthest: {} steps at row {} col {}",
+        solution.farthest_distance, solution.farthest.row, solution.farthest.col
+    );
+    println!("{}", solution.farthest_distance);
+    println!("{}", solution.enclosed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flood_fill_matches_parity_count() {
+        for (data, enclosed) in [
+            (include_str!("test.txt"), 1),
+            (include_str!("test2.txt"), 4),
+            (include_str!("test3.txt"), 10),
+            (include_str!("test4.txt"), 8),
+            (include_str!("test5.txt"), 4),
+        ] {
+            let (solution, grid_mark) = solve(&Grid::new(data).unwrap()).unwrap();
+            assert_eq!(solution.enclosed, enclosed);
+            assert_eq!(grid_mark.enclosed().len(), enclosed);
+        }
+    }
+
+    #[test]
+    fn farthest() {
+        let (solution, _) = solve(&Grid::new(include_str!("test.txt")).unwrap()).unwrap();
+        assert_eq!(solution.length, 16);
+        assert_eq!(solution.farthest_distance, 8);
+        assert_eq!(solution.farthest, Pos { row: 2, col: 4 });
+    }
+
+    fn solve_str(data: &str) -> Result<(Loop, Grid), PipeError> {
+        Grid::new(data).and_then(|grid| solve(&grid))
+    }
+
+    #[test]
+    fn start_shape() {
+        let (_, grid_mark) = solve_str("S-7\n|.|\nL-J\n").unwrap();
+        assert_eq!(grid_mark.at(&Pos { row: 0, col: 0 }).unwrap().c, 'F');
+        // Every neighbor of S here connects to it, but only one shape closes.
+        let (_, grid_mark) = solve_str(include_str!("test3.txt")).unwrap();
+        assert_eq!(grid_mark.at(&Pos { row: 0, col: 4 }).unwrap().c, '7');
+    }
+
+    #[test]
+    fn open_pipe() {
+        let data = ".....\n.S-7.\n.|.|.\n.L-..\n.....\n";
+        match solve_str(data) {
+            Err(PipeError::OpenPipe { end, glyph, next_glyph, .. }) => {
+                assert_eq!(end.steps, 3);
+                assert_eq!(end.pos, Pos { row: 2, col: 3 });
+                assert_eq!(end.next, Pos { row: 3, col: 3 });
+                assert_eq!((glyph, next_glyph), ('|', Some('.')));
+            }
+            other => panic!("{:?}", other.map(|(solution, _)| solution)),
+        }
+    }
 
-    println!("{}", count);
+    #[test]
+    fn open_pipe_at_edge() {
+        match solve_str("S-7\n|.-\nL-J\n") {
+            Err(PipeError::OpenPipe { end, glyph, next_glyph, .. }) => {
+                // The longest path runs round the left side and back up.
+                assert_eq!(end.pos, Pos { row: 2, col: 2 });
+                assert_eq!(end.next, Pos { row: 1, col: 2 });
+                assert_eq!((glyph, next_glyph), ('J', Some('-')));
+            }
+            other => panic!("{:?}", other.map(|(solution, _)| solution)),
+        }
+        match solve_str("S--\n|..\nL--\n") {
+            Err(PipeError::OpenPipe { end, next_glyph, .. }) => {
+                assert_eq!(end.next.col, 3);
+                assert_eq!(next_glyph, None);
+            }
+            other => panic!("{:?}", other.map(|(solution, _)| solution)),
+        }
+    }
+
+    #[test]
+    fn ambiguous_start() {
+        // S closes one loop as J and another as F.
+        let data = ".....\n.F7..\n.LS7.\n..LJ.\n.....\n";
+        match solve_str(data) {
+            Err(PipeError::AmbiguousStart(shapes)) => assert_eq!(shapes, vec!['J', 'F']),
+            other => panic!("{:?}", other.map(|(solution, _)| solution)),
+        }
+    }
+
+    #[test]
+    fn bad_grids() {
+        assert!(matches!(
+            solve_str("S-7\n|X|\nL-J\n"),
+            Err(PipeError::UnknownGlyph { c: 'X', pos: Pos { row: 1, col: 1 } })
+        ));
+        assert!(matches!(
+            solve_str("S-7\n||\nL-J\n"),
+            Err(PipeError::RaggedRow { row: 1, len: 2, expected: 3 })
+        ));
+        assert!(matches!(
+            solve_str("F-7\n|.|\nL-J\n"),
+            Err(PipeError::NoStart)
+        ));
+        assert!(matches!(
+            solve_str("S-7\n|.|\nL-S\n"),
+            Err(PipeError::ExtraStart(Pos { row: 2, col: 2 }))
+        ));
+    }
 }
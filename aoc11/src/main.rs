@@ -1,5 +1,6 @@
 use std::env;
 use std::fs::read_to_string;
+use std::process;
 
 #[derive(Debug)]
 struct Pos {
@@ -36,41 +37,19 @@ impl Grid {
             .collect::<Vec<_>>()
     }
 
-    fn expand(&self) -> (Vec<usize>, Vec<usize>) {
-        let empty_rows: Vec<usize> = self.grid
-            .iter()
-            .enumerate()
-            .filter(|(i, row)| !row.contains(&'#'))
-            .map(|(i, row)| i)
+    // Indices of the rows and columns with no galaxies, which expand.
+    fn empty_lines(&self) -> (Vec<usize>, Vec<usize>) {
+        let empty_rows: Vec<usize> = (0..self.rows)
+            .filter(|row| !self.grid[*row].contains(&'#'))
             .collect::<Vec<_>>();
 
         let empty_cols: Vec<usize> = (0..self.cols)
-            .map(|col| (col, self.col(col)))
-            .filter(|(i, col)| !col.contains(&'#'))
-            .map(|(i, col)| i)
+            .filter(|col| !self.col(*col).contains(&'#'))
             .collect::<Vec<_>>();
-        dbg!(&empty_rows, &empty_cols);
-
-        let mut grid = self.grid.clone();
-        let mut rows = self.rows;
-        let mut cols = self.cols;
-
-        for (i, row) in empty_rows.iter().enumerate() {
-            grid.insert(row + i, vec!['.'; cols]);
-            rows += 1
-        }
-
-        for (i, col) in empty_cols.iter().enumerate() {
-            for row in 0..rows {
-                grid[row].insert(col + i, '.');
-            }
-            cols += 1
-        }
 
         (empty_rows, empty_cols)
     }
 
-
     fn galaxies(&self) -> Vec<Pos> {
         self.grid
             .iter()
@@ -98,41 +77,161 @@ impl Grid {
     }
 }
 
+// The sum of distances between every pair of galaxies, split into the
+// distance before expansion and the number of empty rows and columns
+// crossed, each of which counts factor - 1 extra times once expanded.
+#[derive(Debug, PartialEq)]
+struct Distances {
+    direct: u64,
+    crossed: u64,
+}
+
+impl Distances {
+    // None if the expanded distance doesn't fit in a u64.
+    fn expanded(&self, factor: u64) -> Option<u64> {
+        self.crossed
+            .checked_mul(factor - 1)
+            .and_then(|extra| self.direct.checked_add(extra))
+    }
+}
+
+// Sums |a - b| over every pair of values by sorting them: the k-th smallest
+// value is at least as big as the k before it, so it adds k times itself less
+// their sum.
+fn pair_difference_sum(values: &mut [u64]) -> u64 {
+    values.sort_unstable();
+    let mut sum = 0;
+    let mut prefix = 0;
+    for (k, value) in values.iter().enumerate() {
+        sum += k as u64 * value - prefix;
+        prefix += value;
+    }
+    sum
+}
+
+// Sums distances one axis at a time rather than pair by pair. The empty lines
+// crossed between two galaxies are the difference between the number of
+// empty lines before each, so they sum the same way as the coordinates.
+fn distances(galaxies: &[Pos], empty_rows: &[usize], empty_cols: &[usize]) -> Distances {
+    let mut distances = Distances { direct: 0, crossed: 0 };
+    for (coordinate, empty) in [
+        (galaxies.iter().map(|g| g.row).collect::<Vec<_>>(), empty_rows),
+        (galaxies.iter().map(|g| g.col).collect::<Vec<_>>(), empty_cols),
+    ] {
+        let mut direct: Vec<u64> = coordinate.iter().map(|c| *c as u64).collect();
+        let mut crossed: Vec<u64> = coordinate
+            .iter()
+            .map(|c| empty.partition_point(|e| e < c) as u64)
+            .collect();
+        distances.direct += pair_difference_sum(&mut direct);
+        distances.crossed += pair_difference_sum(&mut crossed);
+    }
+    distances
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = args[1].as_str();
-    let data = read_to_string(&filename).unwrap();
+    let factors: Vec<u64> = args
+        .iter()
+        .position(|a| a == "--expansion")
+        .map_or(vec![2, 1000000], |i| {
+            args[i + 1]
+                .split(',')
+                .map(|factor| factor.parse().unwrap())
+                .collect()
+        });
+    assert!(factors.iter().all(|factor| *factor > 0), "expansion factors start at 1");
+    let data = read_to_string(filename).unwrap();
 
     let grid = Grid::new(&data);
 
-    let (empty_rows, empty_cols) = grid.expand();
+    let (empty_rows, empty_cols) = grid.empty_lines();
 
     let galaxies = grid.galaxies();
-    let mut sum = 0;
-    for i in 0..(galaxies.len() - 1) {
-        for j in (i + 1)..galaxies.len() {
-            let a = &galaxies[i];
-            let b = &galaxies[j];
+    let distances = distances(&galaxies, &empty_rows, &empty_cols);
+    for factor in factors {
+        match distances.expanded(factor) {
+            Some(distance) => println!("{}", distance),
+            None => {
+                eprintln!("distance with expansion factor {} overflows", factor);
+                process::exit(1)
+            }
+        }
+    }
+}
 
-            let empty_rows_crossed = empty_rows
-                .iter()
-                .filter(|&&empty_row| (a.row < empty_row && b.row > empty_row)
-                    || (b.row < empty_row && a.row > empty_row))
-                .count();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairwise_distances(galaxies: &[Pos], empty_rows: &[usize], empty_cols: &[usize]) -> Distances {
+        let mut distances = Distances { direct: 0, crossed: 0 };
+        for i in 0..galaxies.len() {
+            for j in (i + 1)..galaxies.len() {
+                let a = &galaxies[i];
+                let b = &galaxies[j];
+
+                let empty_rows_crossed = empty_rows
+                    .iter()
+                    .filter(|&&empty_row| (a.row < empty_row && b.row > empty_row)
+                        || (b.row < empty_row && a.row > empty_row))
+                    .count();
+
+                let empty_cols_crossed = empty_cols
+                    .iter()
+                    .filter(|&&empty_col| (a.col < empty_col && b.col > empty_col)
+                        || (b.col < empty_col && a.col > empty_col))
+                    .count();
+
+                distances.direct += (a.row.abs_diff(b.row) + a.col.abs_diff(b.col)) as u64;
+                distances.crossed += (empty_rows_crossed + empty_cols_crossed) as u64;
+            }
+        }
+        distances
+    }
 
-            let empty_cols_crossed = empty_cols
-                .iter()
-                .filter(|&&empty_col| (a.col < empty_col && b.col > empty_col)
-                    || (b.col < empty_col && a.col > empty_col))
-                .count();
-
-            let expansion = 1000000;
-            let dist = (a.row as i64 - b.row as i64).abs() as usize +
-                empty_rows_crossed * (expansion - 1) +
-                (a.col as i64 - b.col as i64).abs() as usize +
-                empty_cols_crossed * (expansion - 1);
-            sum += dist;
+    fn assert_agrees(data: &str) {
+        let grid = Grid::new(data);
+        let (empty_rows, empty_cols) = grid.empty_lines();
+        let galaxies = grid.galaxies();
+        assert_eq!(
+            distances(&galaxies, &empty_rows, &empty_cols),
+            pairwise_distances(&galaxies, &empty_rows, &empty_cols)
+        );
+    }
+
+    #[test]
+    fn example() {
+        let grid = Grid::new(include_str!("test.txt"));
+        let (empty_rows, empty_cols) = grid.empty_lines();
+        assert_eq!((empty_rows.clone(), empty_cols.clone()), (vec![3, 7], vec![2, 5, 8]));
+        let distances = distances(&grid.galaxies(), &empty_rows, &empty_cols);
+        assert_eq!(distances.expanded(2), Some(374));
+        assert_eq!(distances.expanded(10), Some(1030));
+        assert_eq!(distances.expanded(100), Some(8410));
+        assert_eq!(distances.expanded(u64::MAX), None);
+        assert_agrees(include_str!("test.txt"));
+    }
+
+    #[test]
+    fn distances_match_pairwise() {
+        // A small deterministic scatter of galaxies with empty rows and
+        // columns between them.
+        let mut state: u64 = 1;
+        for size in [1, 2, 5, 17, 40] {
+            let data: String = (0..size)
+                .map(|_| {
+                    (0..size)
+                        .map(|_| {
+                            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                            if state >> 60 == 0 { '#' } else { '.' }
+                        })
+                        .collect::<String>()
+                        + "\n"
+                })
+                .collect();
+            assert_agrees(&data);
         }
     }
-    println!("{}", sum);
 }